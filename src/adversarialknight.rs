@@ -1,6 +1,6 @@
-use std::fmt::Display;
+use std::{fmt::Display, hash::{Hash, Hasher}};
 
-use crate::game::{Game, ToMove, Keyed};
+use crate::{game::{Game, ToMove, Keyed, Outcome, Player}, tensor::TensorEncode};
 
 macro_rules! cfor {
     ($init: stmt; $cond: expr; $step: expr; $body: block) => {
@@ -27,11 +27,29 @@ const fn init_hash_keys() -> [u64; 64] {
 
 static KNIGHTLOC_HASHKEYS: [u64; 64] = init_hash_keys();
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone)]
 pub struct AdversarialKnight {
     knightloc: isize,
     moves: usize,
     visited: u64,
+    // the squares the knight has left, so that pop can put it back.
+    trail: Vec<isize>,
+}
+
+// the trail only says how the position was reached, so it is left out
+// when comparing and hashing positions.
+impl PartialEq for AdversarialKnight {
+    fn eq(&self, other: &Self) -> bool {
+        (self.knightloc, self.moves, self.visited) == (other.knightloc, other.moves, other.visited)
+    }
+}
+
+impl Eq for AdversarialKnight {}
+
+impl Hash for AdversarialKnight {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.knightloc, self.moves, self.visited).hash(state);
+    }
 }
 
 impl AdversarialKnight {
@@ -40,6 +58,7 @@ impl AdversarialKnight {
             knightloc: 32,
             moves: 0,
             visited: KNIGHTLOC_HASHKEYS[32],
+            trail: Vec::new(),
         }
     }
 
@@ -73,17 +92,18 @@ impl Game for AdversarialKnight {
         }
     }
 
-    fn evaluate(&self) -> i8 {
+    fn outcome(&self) -> Option<Outcome> {
         let mut buf = Vec::with_capacity(8);
         self.generate_moves(&mut buf);
         if buf.is_empty() {
-            return -self.turn();
+            // the player who can't move loses.
+            let stuck = if self.moves & 1 == 0 { Player::X } else { Player::O };
+            return Some(Outcome::Win(stuck.opponent()));
         }
-        0
-    }
-
-    fn is_terminal(&self) -> bool {
-        self.moves == 64 || self.evaluate() != 0
+        if self.moves == 64 {
+            return Some(Outcome::Draw);
+        }
+        None
     }
 
     fn generate_moves(&self, buffer: &mut Vec<Self::Move>) {
@@ -97,13 +117,15 @@ impl Game for AdversarialKnight {
     }
 
     fn push(&mut self, m: Self::Move) {
+        self.trail.push(self.knightloc);
         self.knightloc = m as isize;
         self.moves += 1;
-        self.visited ^= KNIGHTLOC_HASHKEYS[m as usize];
+        self.visited ^= KNIGHTLOC_HASHKEYS[m];
     }
 
-    fn pop(&mut self, m: Self::Move) {
+    fn pop(&mut self, _m: Self::Move) {
         self.visited ^= KNIGHTLOC_HASHKEYS[self.knightloc as usize];
+        self.knightloc = self.trail.pop().expect("pop without a move to take back");
         self.moves -= 1;
     }
 
@@ -111,6 +133,14 @@ impl Game for AdversarialKnight {
         8
     }
 
+    fn move_index(&self, m: Self::Move) -> usize {
+        let offset = m as isize - self.knightloc;
+        KNIGHT_MOVE_OFFSETS
+            .iter()
+            .position(|&o| o == offset)
+            .expect("not a knight move")
+    }
+
     fn to_move(&self) -> crate::game::ToMove {
         if self.moves & 1 == 0 {
             ToMove::Max
@@ -120,6 +150,20 @@ impl Game for AdversarialKnight {
     }
 }
 
+impl TensorEncode for AdversarialKnight {
+    fn tensor_shape(&self) -> Vec<usize> {
+        vec![2, 8, 8]
+    }
+
+    fn encode_tensor(&self) -> Vec<f32> {
+        // a plane of the visited squares, and one with the knight. both
+        // players move the same knight, so neither plane belongs to a side.
+        let visited = (0..64).map(|loc| self.visited(loc) as u8 as f32);
+        let knight = (0..64).map(|loc| (loc == self.knightloc) as u8 as f32);
+        visited.chain(knight).collect()
+    }
+}
+
 impl Keyed for AdversarialKnight {
     fn hashkey(&self) -> u64 {
         self.visited
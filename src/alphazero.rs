@@ -0,0 +1,716 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display, Formatter},
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    dataset::{self, DatasetConfig, Sample},
+    driver,
+    game::Keyed,
+    io::{load, LoadError, ParseError},
+    tensor::TensorEncode,
+    solver::SolverPolicy,
+    winprob::{favourite_move, Policy},
+};
+
+// AlphaZero in miniature, for the small games without chance. a network reads
+// the encoded position and gives a prior over the moves and a value for the
+// side to move, which steer a tree search in place of random playouts. the
+// network then learns from games that the search plays against itself: the
+// priors from the search's visit counts, and the value from the results.
+
+// a multi-layer perceptron with one tanh hidden layer and two heads: the
+// policy, a logit for every action index, and the value, a tanh unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyValueNet {
+    inputs: usize,
+    hidden: usize,
+    actions: usize,
+    // the hidden weights row by row and the hidden biases, the policy weights
+    // row by row and the policy biases, then the value weights and bias.
+    params: Vec<f64>,
+}
+
+// the parts of params, as offsets into it.
+struct Layout {
+    hidden_biases: usize,
+    policy_weights: usize,
+    policy_biases: usize,
+    value_weights: usize,
+    value_bias: usize,
+    len: usize,
+}
+
+// the outputs of a forward pass, with the hidden activations for training.
+struct Forward {
+    hidden: Vec<f64>,
+    logits: Vec<f64>,
+    value: f64,
+}
+
+fn softmax(logits: &[f64]) -> Vec<f64> {
+    let max = logits.iter().copied().fold(f64::MIN, f64::max);
+    let exps = logits.iter().map(|&l| (l - max).exp()).collect::<Vec<_>>();
+    let total = exps.iter().sum::<f64>();
+    exps.into_iter().map(|e| e / total).collect()
+}
+
+impl PolicyValueNet {
+    // a network with random weights scaled by the fan-in of each layer, and
+    // biases of zero.
+    pub fn new<R: Rng>(inputs: usize, hidden: usize, actions: usize, rng: &mut R) -> Self {
+        let mut net = Self { inputs, hidden, actions, params: Vec::new() };
+        let layout = net.layout();
+        let mut params = vec![0.0; layout.len];
+        let input_scale = 1.0 / (inputs as f64).sqrt();
+        let hidden_scale = 1.0 / (hidden as f64).sqrt();
+        for (i, p) in params.iter_mut().enumerate() {
+            if i < layout.hidden_biases {
+                *p = rng.gen_range(-input_scale..input_scale);
+            } else if (layout.policy_weights..layout.policy_biases).contains(&i) || (layout.value_weights..layout.value_bias).contains(&i) {
+                *p = rng.gen_range(-hidden_scale..hidden_scale);
+            }
+        }
+        net.params = params;
+        net
+    }
+
+    // a network sized for the game's encoding and actions.
+    pub fn for_game<G: TensorEncode, R: Rng>(game: &G, hidden: usize, rng: &mut R) -> Self {
+        Self::new(game.tensor_shape().iter().product(), hidden, game.action_space_size(), rng)
+    }
+
+    fn layout(&self) -> Layout {
+        let hidden_biases = self.hidden * self.inputs;
+        let policy_weights = hidden_biases + self.hidden;
+        let policy_biases = policy_weights + self.actions * self.hidden;
+        let value_weights = policy_biases + self.actions;
+        let value_bias = value_weights + self.hidden;
+        Layout { hidden_biases, policy_weights, policy_biases, value_weights, value_bias, len: value_bias + 1 }
+    }
+
+    fn forward(&self, x: &[f32]) -> Forward {
+        assert_eq!(x.len(), self.inputs);
+        let layout = self.layout();
+        let p = &self.params;
+        let hidden = p[..layout.hidden_biases]
+            .chunks(self.inputs)
+            .zip(&p[layout.hidden_biases..layout.policy_weights])
+            .map(|(row, bias)| (row.iter().zip(x).map(|(w, &xi)| w * xi as f64).sum::<f64>() + bias).tanh())
+            .collect::<Vec<_>>();
+        let logits = p[layout.policy_weights..layout.policy_biases]
+            .chunks(self.hidden)
+            .zip(&p[layout.policy_biases..layout.value_weights])
+            .map(|(row, bias)| row.iter().zip(&hidden).map(|(w, h)| w * h).sum::<f64>() + bias)
+            .collect();
+        let value_input = p[layout.value_weights..layout.value_bias].iter().zip(&hidden).map(|(w, h)| w * h).sum::<f64>();
+        let value = (value_input + p[layout.value_bias]).tanh();
+        Forward { hidden, logits, value }
+    }
+
+    // the prior for each of the moves, which sum to one, and the value of the
+    // position for the side to move, from -1 for a loss to 1 for a win.
+    pub fn evaluate<G: TensorEncode>(&self, game: &G, moves: &[G::Move]) -> (Vec<f64>, f64) {
+        let forward = self.forward(&game.encode_tensor());
+        let logits = moves.iter().map(|&m| forward.logits[game.action_index(m)]).collect::<Vec<_>>();
+        (softmax(&logits), forward.value)
+    }
+
+    // the squared error of the value and the cross-entropy of the policy
+    // against the sample, and the gradient of their sum.
+    fn loss_and_gradient(&self, sample: &Sample) -> (f64, f64, Vec<f64>) {
+        let layout = self.layout();
+        let Forward { hidden, logits, value } = self.forward(&sample.encoding);
+        let z = sample.outcome as f64;
+        let probs = softmax(&logits);
+        let target_total = sample.policy.iter().map(|&t| t as f64).sum::<f64>();
+        let value_loss = (value - z).powi(2);
+        let policy_loss = -sample.policy.iter().zip(&probs).filter(|&(&t, _)| t > 0.0).map(|(&t, p)| t as f64 * p.ln()).sum::<f64>();
+
+        let d_logits = probs.iter().zip(&sample.policy).map(|(p, &t)| p * target_total - t as f64).collect::<Vec<_>>();
+        let d_value = 2.0 * (value - z) * (1.0 - value * value);
+        let policy_weights = &self.params[layout.policy_weights..layout.policy_biases];
+        let value_weights = &self.params[layout.value_weights..layout.value_bias];
+        let d_hidden = (0..self.hidden)
+            .map(|j| {
+                let from_policy = d_logits.iter().enumerate().map(|(a, d)| d * policy_weights[a * self.hidden + j]).sum::<f64>();
+                (from_policy + d_value * value_weights[j]) * (1.0 - hidden[j] * hidden[j])
+            })
+            .collect::<Vec<_>>();
+
+        let mut grad = Vec::with_capacity(layout.len);
+        for d in &d_hidden {
+            grad.extend(sample.encoding.iter().map(|&xi| d * xi as f64));
+        }
+        grad.extend(&d_hidden);
+        for d in &d_logits {
+            grad.extend(hidden.iter().map(|h| d * h));
+        }
+        grad.extend(&d_logits);
+        grad.extend(hidden.iter().map(|h| d_value * h));
+        grad.push(d_value);
+        (value_loss, policy_loss, grad)
+    }
+
+    // one step of gradient descent on the mean loss over the batch, with
+    // weight decay. returns the mean value and policy losses before the step.
+    pub fn train_batch(&mut self, batch: &[&Sample], learning_rate: f64, weight_decay: f64) -> (f64, f64) {
+        let mut total = vec![0.0; self.params.len()];
+        let (mut value_loss, mut policy_loss) = (0.0, 0.0);
+        for sample in batch {
+            let (v, p, grad) = self.loss_and_gradient(sample);
+            value_loss += v;
+            policy_loss += p;
+            for (t, g) in total.iter_mut().zip(grad) {
+                *t += g;
+            }
+        }
+        let n = batch.len() as f64;
+        for (p, g) in self.params.iter_mut().zip(total) {
+            *p -= learning_rate * (g / n + weight_decay * *p);
+        }
+        (value_loss / n, policy_loss / n)
+    }
+
+    // reads a network written by Display: a header line
+    // "pvnet <inputs> <hidden> <actions>", then the parameters.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let err = |line, message: &str| ParseError { line, message: message.to_string() };
+        let mut lines = text.lines();
+        let header = lines.next().unwrap_or("").split_whitespace().collect::<Vec<_>>();
+        let size = |word: &str, what| word.parse::<usize>().map_err(|_| err(1, what));
+        let mut net = match header[..] {
+            ["pvnet", inputs, hidden, actions] => Self {
+                inputs: size(inputs, "invalid input count")?,
+                hidden: size(hidden, "invalid hidden count")?,
+                actions: size(actions, "invalid action count")?,
+                params: Vec::new(),
+            },
+            _ => return Err(err(1, "expected \"pvnet <inputs> <hidden> <actions>\"")),
+        };
+        for (line, text) in lines.enumerate().map(|(i, l)| (i + 2, l)) {
+            for word in text.split_whitespace() {
+                net.params.push(word.parse().map_err(|_| err(line, "invalid parameter"))?);
+            }
+        }
+        if net.params.len() != net.layout().len {
+            return Err(err(1, "wrong number of parameters"));
+        }
+        Ok(net)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        load(path, Self::parse)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl Display for PolicyValueNet {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // a line for each hidden unit's weights and one for the hidden
+        // biases, the same for the policy head, then the value weights and bias.
+        writeln!(f, "pvnet {} {} {}", self.inputs, self.hidden, self.actions)?;
+        let layout = self.layout();
+        let p = &self.params;
+        let rows = p[..layout.hidden_biases]
+            .chunks(self.inputs)
+            .chain([&p[layout.hidden_biases..layout.policy_weights]])
+            .chain(p[layout.policy_weights..layout.policy_biases].chunks(self.hidden))
+            .chain([&p[layout.policy_biases..layout.value_weights], &p[layout.value_weights..layout.value_bias], &p[layout.value_bias..]]);
+        for row in rows {
+            let row = row.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+// Dirichlet noise mixed into the priors at the root, so that self-play
+// tries moves that the network doesn't yet like.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RootNoise {
+    // the concentration of the noise. smaller values put it on fewer moves.
+    pub alpha: f64,
+    // the share of each prior that comes from the noise.
+    pub fraction: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PuctConfig {
+    pub playouts: usize,
+    // the weight of the prior against the values found so far. larger
+    // values follow the network's priors further.
+    pub c_puct: f64,
+    pub noise: Option<RootNoise>,
+}
+
+impl PuctConfig {
+    pub fn new(playouts: usize) -> Self {
+        Self { playouts, c_puct: 1.5, noise: None }
+    }
+
+    pub fn with_noise(self, noise: RootNoise) -> Self {
+        Self {
+            noise: Some(noise),
+            ..self
+        }
+    }
+}
+
+// a draw from the gamma distribution with the given shape and a scale of
+// one, by the method of Marsaglia and Tsang.
+fn gamma<R: Rng>(shape: f64, rng: &mut R) -> f64 {
+    if shape < 1.0 {
+        // draw with the shape raised by one, and scale it back down.
+        return gamma(shape + 1.0, rng) * rng.gen::<f64>().powf(1.0 / shape);
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        // a standard normal, by the Box-Muller transform.
+        let x = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt() * (std::f64::consts::TAU * rng.gen::<f64>()).cos();
+        let v = (1.0 + c * x).powi(3);
+        if v > 0.0 && rng.gen::<f64>().ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
+}
+
+// n probabilities from the symmetric Dirichlet distribution.
+fn dirichlet<R: Rng>(alpha: f64, n: usize, rng: &mut R) -> Vec<f64> {
+    let draws = (0..n).map(|_| gamma(alpha, rng)).collect::<Vec<_>>();
+    let total = draws.iter().sum::<f64>();
+    if total > 0.0 {
+        draws.into_iter().map(|g| g / total).collect()
+    } else {
+        vec![1.0 / n as f64; n]
+    }
+}
+
+struct Node<M> {
+    // the move that leads here from the parent, None at the root.
+    m: Option<M>,
+    // +1 if X made that move, and -1 if O did.
+    mover: i8,
+    prior: f64,
+    children: Vec<usize>,
+    expanded: bool,
+    visits: u32,
+    // the values backed up through this node for the mover, from -1 to 1.
+    value: f64,
+}
+
+impl<M> Node<M> {
+    fn new(m: Option<M>, mover: i8, prior: f64) -> Self {
+        Self { m, mover, prior, children: Vec::new(), expanded: false, visits: 0, value: 0.0 }
+    }
+
+    // the PUCT score: the mean value so far, counting an unvisited move as
+    // even, plus a bonus for moves the network likes that have few visits.
+    fn score(&self, sqrt_parent_visits: f64, c_puct: f64) -> f64 {
+        let mean = if self.visits > 0 { self.value / self.visits as f64 } else { 0.0 };
+        mean + c_puct * self.prior * sqrt_parent_visits / (1 + self.visits) as f64
+    }
+}
+
+// the number of playouts that went through each of the moves at the root,
+// in the order that the game generates them. each playout walks down the tree
+// by the PUCT score, and the network judges the position where it leaves the
+// tree, so no game is played out. finished games are scored by their result.
+// with root noise in the config, the rng draws the noise.
+pub fn puct<G: TensorEncode, R: Rng>(game: &G, net: &PolicyValueNet, config: PuctConfig, rng: &mut R) -> Vec<(G::Move, u32)> {
+    let mut nodes = vec![Node::new(None, -game.turn(), 1.0)];
+    let mut path = Vec::new();
+    let mut moves = Vec::with_capacity(game.action_space_size());
+    for _ in 0..config.playouts {
+        let mut game = game.clone();
+        path.clear();
+        path.push(0);
+        let mut current = 0;
+        while nodes[current].expanded && !nodes[current].children.is_empty() {
+            let sqrt_visits = (nodes[current].visits as f64).sqrt();
+            current = *nodes[current]
+                .children
+                .iter()
+                .max_by(|&&a, &&b| {
+                    let a = nodes[a].score(sqrt_visits, config.c_puct);
+                    let b = nodes[b].score(sqrt_visits, config.c_puct);
+                    a.total_cmp(&b)
+                })
+                .unwrap();
+            game.push(nodes[current].m.unwrap());
+            path.push(current);
+        }
+        // the value of the leaf for X. a position with no moves that isn't
+        // over counts as a draw.
+        let x_value = match game.outcome() {
+            Some(outcome) => outcome.sign() as f64,
+            None => {
+                moves.clear();
+                game.generate_moves(&mut moves);
+                if moves.is_empty() {
+                    0.0
+                } else {
+                    let (mut priors, value) = net.evaluate(&game, &moves);
+                    if let (0, Some(noise)) = (current, config.noise) {
+                        for (prior, eta) in priors.iter_mut().zip(dirichlet(noise.alpha, moves.len(), rng)) {
+                            *prior = (1.0 - noise.fraction) * *prior + noise.fraction * eta;
+                        }
+                    }
+                    for (&m, prior) in moves.iter().zip(priors) {
+                        nodes.push(Node::new(Some(m), game.turn(), prior));
+                        let child = nodes.len() - 1;
+                        nodes[current].children.push(child);
+                    }
+                    value * game.turn() as f64
+                }
+            },
+        };
+        nodes[current].expanded = true;
+        for &i in &path {
+            let node = &mut nodes[i];
+            node.visits += 1;
+            node.value += x_value * node.mover as f64;
+        }
+    }
+    moves.clear();
+    game.generate_moves(&mut moves);
+    moves
+        .into_iter()
+        .map(|m| {
+            let index = game.move_index(m);
+            let visits = nodes[0]
+                .children
+                .iter()
+                .map(|&i| &nodes[i])
+                .find(|child| game.move_index(child.m.unwrap()) == index)
+                .map_or(0, |child| child.visits);
+            (m, visits)
+        })
+        .collect()
+}
+
+// plays the move that the network-guided search visits most.
+#[derive(Debug, Clone)]
+pub struct AzPolicy {
+    pub net: Arc<PolicyValueNet>,
+    pub config: PuctConfig,
+}
+
+impl<G: TensorEncode> Policy<G> for AzPolicy {
+    fn choose<R: Rng>(&mut self, game: &G, moves: &[G::Move], rng: &mut R) -> G::Move {
+        let distribution = self.distribution(game, moves, rng);
+        favourite_move(moves, &distribution, rng)
+    }
+
+    // the share of the playouts that went through each move.
+    fn distribution<R: Rng>(&mut self, game: &G, moves: &[G::Move], rng: &mut R) -> Vec<f64> {
+        let visits = puct(game, &self.net, self.config, rng);
+        let total = visits.iter().map(|&(_, n)| n as f64).sum::<f64>();
+        if total == 0.0 {
+            return vec![1.0 / moves.len() as f64; moves.len()];
+        }
+        moves
+            .iter()
+            .map(|&m| {
+                let index = game.move_index(m);
+                visits.iter().find(|&&(v, _)| game.move_index(v) == index).map_or(0.0, |&(_, n)| n as f64 / total)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrainConfig {
+    pub iterations: usize,
+    // the self-play games played by each iteration.
+    pub games: usize,
+    // the search in self-play and in evaluation matches, where it plays
+    // without the noise.
+    pub search: PuctConfig,
+    pub hidden: usize,
+    // the moves at the start of each self-play game that are drawn from the
+    // visit counts rather than played greedily.
+    pub sample_plies: usize,
+    // passes over the training window after each iteration's games.
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f64,
+    pub weight_decay: f64,
+    // the network trains on the samples of this many of the latest iterations.
+    pub window: usize,
+    // pairs of games in each evaluation match.
+    pub eval_pairs: usize,
+    // how far the solver searches in its evaluation matches. None solves
+    // every move exactly, which only the smallest games allow.
+    pub solver_depth: Option<usize>,
+    pub seed: u64,
+}
+
+impl TrainConfig {
+    pub fn new(iterations: usize, seed: u64) -> Self {
+        Self {
+            iterations,
+            games: 100,
+            search: PuctConfig::new(64).with_noise(RootNoise { alpha: 0.5, fraction: 0.25 }),
+            hidden: 64,
+            sample_plies: 30,
+            epochs: 4,
+            batch_size: 32,
+            learning_rate: 0.05,
+            weight_decay: 1e-4,
+            window: 4,
+            eval_pairs: 20,
+            solver_depth: None,
+            seed,
+        }
+    }
+
+    pub fn with_games(self, games: usize) -> Self {
+        Self {
+            games,
+            ..self
+        }
+    }
+
+    pub fn with_playouts(self, playouts: usize) -> Self {
+        Self {
+            search: PuctConfig { playouts, ..self.search },
+            ..self
+        }
+    }
+
+    pub fn with_hidden(self, hidden: usize) -> Self {
+        Self {
+            hidden,
+            ..self
+        }
+    }
+
+    pub fn with_eval_pairs(self, eval_pairs: usize) -> Self {
+        Self {
+            eval_pairs,
+            ..self
+        }
+    }
+
+    pub fn with_solver_depth(self, depth: usize) -> Self {
+        Self {
+            solver_depth: Some(depth),
+            ..self
+        }
+    }
+}
+
+// what one iteration of training did: a point on the learning curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IterationReport {
+    pub iteration: usize,
+    pub samples: usize,
+    // the mean losses over the last epoch.
+    pub value_loss: f64,
+    pub policy_loss: f64,
+    // the new network's results, searching as in self-play, against the
+    // network before this iteration and against the solver.
+    pub against_previous: driver::MatchScore,
+    pub against_solver: driver::MatchScore,
+    pub seconds: f64,
+}
+
+impl IterationReport {
+    pub const HEADER: &'static str = "iteration\tsamples\tvalue loss\tpolicy loss\tvs previous\tvs solver\tseconds";
+}
+
+impl Display for IterationReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{:.4}\t{:.4}\t{:.3}\t{:.3}\t{:.1}",
+            self.iteration,
+            self.samples,
+            self.value_loss,
+            self.policy_loss,
+            self.against_previous.score(),
+            self.against_solver.score(),
+            self.seconds
+        )
+    }
+}
+
+// trains the network from its own games. after every iteration the network is
+// matched against the one before and against the solver, and `progress` is
+// told how it went. with a checkpoint directory, each iteration's network is
+// saved there as `net-<iteration>.txt`, and the learning curve is kept up to
+// date in `curve.tsv`.
+pub fn train<G, F>(game: &G, net: PolicyValueNet, config: TrainConfig, checkpoints: Option<&Path>, mut progress: F) -> io::Result<(PolicyValueNet, Vec<IterationReport>)>
+where
+    G: TensorEncode + Keyed,
+    F: FnMut(&IterationReport),
+{
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut net = net;
+    let mut window = VecDeque::new();
+    let mut reports = Vec::new();
+    if let Some(dir) = checkpoints {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join("curve.tsv"), format!("{}\n", IterationReport::HEADER))?;
+    }
+    for iteration in 1..=config.iterations {
+        let start = Instant::now();
+        let previous = Arc::new(net.clone());
+        let mut agent = AzPolicy { net: previous.clone(), config: config.search };
+        let selfplay = DatasetConfig::new(config.games, rng.gen()).with_sample_plies(config.sample_plies);
+        let samples = dataset::generate(game, &mut agent, selfplay);
+        let new_samples = samples.len();
+        window.push_back(samples);
+        if window.len() > config.window {
+            window.pop_front();
+        }
+
+        let mut batch = window.iter().flatten().collect::<Vec<_>>();
+        let (mut value_loss, mut policy_loss) = (0.0, 0.0);
+        for _ in 0..config.epochs {
+            batch.shuffle(&mut rng);
+            let (mut value_total, mut policy_total) = (0.0, 0.0);
+            for chunk in batch.chunks(config.batch_size) {
+                let (v, p) = net.train_batch(chunk, config.learning_rate, config.weight_decay);
+                value_total += v * chunk.len() as f64;
+                policy_total += p * chunk.len() as f64;
+            }
+            value_loss = value_total / batch.len() as f64;
+            policy_loss = policy_total / batch.len() as f64;
+        }
+
+        let evaluation = PuctConfig { noise: None, ..config.search };
+        let mut agent = AzPolicy { net: previous, config: evaluation };
+        let mut trained = AzPolicy { net: Arc::new(net.clone()), config: evaluation };
+        let against_previous = driver::play_match_without_chance(game, &mut trained, &mut agent, config.eval_pairs, 2, rng.gen());
+        let mut solver = config.solver_depth.map_or_else(SolverPolicy::new, SolverPolicy::with_depth);
+        let against_solver = driver::play_match_without_chance(game, &mut trained, &mut solver, config.eval_pairs, 2, rng.gen());
+
+        let report = IterationReport {
+            iteration,
+            samples: new_samples,
+            value_loss,
+            policy_loss,
+            against_previous,
+            against_solver,
+            seconds: start.elapsed().as_secs_f64(),
+        };
+        if let Some(dir) = checkpoints {
+            net.save(dir.join(format!("net-{:03}.txt", iteration)))?;
+            let mut curve = OpenOptions::new().append(true).open(dir.join("curve.tsv"))?;
+            writeln!(curve, "{}", report)?;
+        }
+        progress(&report);
+        reports.push(report);
+    }
+    Ok((net, reports))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::dataset::{generate, DatasetConfig, Sample};
+    use crate::driver::play_match_without_chance;
+    use crate::game::Game;
+    use crate::testing::{assert_gradient_matches, assert_round_trip};
+    use crate::tictactoe::{TicTacToe, TicTacToeMove};
+    use crate::solver::SolverPolicy;
+    use crate::winprob::RandomPolicy;
+
+    use super::{puct, train, AzPolicy, PolicyValueNet, PuctConfig, TrainConfig};
+
+    #[test]
+    fn gradient_matches_differences() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let net = PolicyValueNet::new(4, 3, 3, &mut rng);
+        let sample = Sample { encoding: vec![1.0, 0.0, -0.5, 2.0], policy: vec![0.25, 0.0, 0.75], outcome: -1.0, ply: 0, key: 0 };
+        let (_, _, grad) = net.loss_and_gradient(&sample);
+        let loss = |params: &[f64]| {
+            let (v, p, _) = PolicyValueNet { params: params.to_vec(), ..net.clone() }.loss_and_gradient(&sample);
+            v + p
+        };
+        assert_gradient_matches(&net.params, &grad, loss, 1e-4);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let net = PolicyValueNet::for_game(&TicTacToe::new(), 5, &mut rng);
+        assert_round_trip(&net, PolicyValueNet::parse);
+        assert_eq!(net.to_string().lines().count(), 1 + 5 + 1 + 9 + 1 + 1 + 1);
+        assert!(PolicyValueNet::parse("pvnet 2 1 1\n0 0 0").is_err());
+        assert!(PolicyValueNet::parse("mlp 2 1\n0 0 0 0 0 0").is_err());
+    }
+
+    #[test]
+    fn learns_from_samples() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let samples = generate(&TicTacToe::new(), &mut SolverPolicy::new(), DatasetConfig::new(20, 3));
+        let batch = samples.iter().collect::<Vec<_>>();
+        let mut net = PolicyValueNet::for_game(&TicTacToe::new(), 16, &mut rng);
+        let (value_before, policy_before) = net.train_batch(&batch, 0.0, 0.0);
+        for _ in 0..300 {
+            net.train_batch(&batch, 0.1, 0.0);
+        }
+        let (value_after, policy_after) = net.train_batch(&batch, 0.0, 0.0);
+        assert!(value_after < value_before / 2.0, "{} -> {}", value_before, value_after);
+        // the solver breaks ties at random, so the policy can't be learnt exactly.
+        assert!(policy_after < policy_before * 0.8, "{} -> {}", policy_before, policy_after);
+    }
+
+    #[test]
+    fn search_finds_wins() {
+        // X, with two in the top row, finishes it, whatever an untrained
+        // network thinks, as the search sees the result.
+        let mut rng = StdRng::seed_from_u64(4);
+        let net = PolicyValueNet::for_game(&TicTacToe::new(), 8, &mut rng);
+        let mut game = TicTacToe::new();
+        for m in [0, 3, 1, 4] {
+            game.push(TicTacToeMove::new(m));
+        }
+        let visits = puct(&game, &net, PuctConfig::new(100), &mut rng);
+        assert_eq!(visits.iter().map(|&(_, n)| n).sum::<u32>(), 99);
+        let win = visits.iter().find(|&&(m, _)| m == TicTacToeMove::new(2)).unwrap().1;
+        assert!(visits.iter().all(|&(_, n)| n <= win), "{:?}", visits);
+
+        let mut agent = AzPolicy { net: Arc::new(net), config: PuctConfig::new(50) };
+        let score = play_match_without_chance(&TicTacToe::new(), &mut agent, &mut RandomPolicy, 3, 2, 4);
+        assert_eq!(score.games(), 6);
+    }
+
+    #[test]
+    fn training_loop() {
+        let dir = std::env::temp_dir().join(format!("alphazero-test-{}", std::process::id()));
+        let config = TrainConfig::new(2, 5).with_games(6).with_playouts(16).with_hidden(8).with_eval_pairs(1);
+        let mut rng = StdRng::seed_from_u64(5);
+        let net = PolicyValueNet::for_game(&TicTacToe::new(), config.hidden, &mut rng);
+        let mut seen = Vec::new();
+        let (net, reports) = train(&TicTacToe::new(), net, config, Some(&dir), |report| seen.push(report.iteration)).unwrap();
+        assert_eq!(seen, vec![1, 2]);
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|report| report.samples >= 6 * 5));
+        for report in &reports {
+            assert_eq!(report.against_previous.games(), 2);
+            assert_eq!(report.against_solver.games(), 2);
+        }
+        assert_eq!(PolicyValueNet::load(dir.join("net-002.txt")).unwrap(), net);
+        let curve = std::fs::read_to_string(dir.join("curve.tsv")).unwrap();
+        assert_eq!(curve.lines().count(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
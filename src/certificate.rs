@@ -0,0 +1,308 @@
+use std::{collections::HashMap, fmt::{self, Display, Formatter}};
+
+use crate::{game::{Keyed, Outcome, Player}, io::ParseError, solver::{negamax, solve, SearchContext, INF}};
+
+// a proof certificate for a forced win.
+// for every position reachable when the winner follows the strategy,
+// the certificate records the winner's move (in move notation), keyed
+// by the position's hashkey. the loser's replies are not recorded, as
+// the verifier enumerates all of them itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    pub winner: Player,
+    // the number of plies within which the win is claimed.
+    pub plies: usize,
+    pub strategy: HashMap<u64, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    // the strategy has no move for a position where the winner is to move.
+    MissingMove { key: u64 },
+    // the strategy's move for this position is not among the legal moves.
+    IllegalMove { key: u64, notation: String },
+    // a line of play ended without a win for the claimed winner.
+    // the outcome is None if the game stopped with no moves left.
+    WrongResult { key: u64, outcome: Option<Outcome> },
+    // a line of play went on for longer than the claimed number of plies.
+    TooLong { key: u64 },
+    // every line ends in a win, but the longest line is not the claimed length.
+    DepthMismatch { claimed: usize, actual: usize },
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            VerifyError::MissingMove { key } => write!(f, "no move for position {:016x}", key),
+            VerifyError::IllegalMove { key, notation } => write!(f, "illegal move {} in position {:016x}", notation, key),
+            VerifyError::WrongResult { key, outcome: Some(outcome) } => write!(f, "line ends in position {:016x} with result {}", key, outcome),
+            VerifyError::WrongResult { key, outcome: None } => write!(f, "line ends in position {:016x} with no legal moves", key),
+            VerifyError::TooLong { key } => write!(f, "line exceeds the claimed length at position {:016x}", key),
+            VerifyError::DepthMismatch { claimed, actual } => write!(f, "claimed a win in {} plies, but the longest line is {} plies", claimed, actual),
+        }
+    }
+}
+
+impl Certificate {
+    // builds a certificate for the side that wins the given position,
+    // or returns None if the position is a draw.
+    pub fn generate<G: Keyed>(game: G) -> Option<Self> {
+        let solution = solve(game.clone());
+        if solution == 0 {
+            return None;
+        }
+        let mut cert = Self {
+            winner: if solution > 0 { Player::X } else { Player::O },
+            plies: solution.unsigned_abs() as usize,
+            strategy: HashMap::new(),
+        };
+        let mut game = game;
+        let plies = cert.plies;
+        cert.extend(&mut game, plies, &mut HashMap::new());
+        Some(cert)
+    }
+
+    // `budgets` holds, for each position in the strategy, the plies its
+    // move was chosen to win within. a move that wins within fewer plies
+    // wins within more, so a position is only looked at again when it is
+    // reached with a smaller budget.
+    fn extend<G: Keyed>(&mut self, node: &mut G, plies_left: usize, budgets: &mut HashMap<u64, usize>) {
+        // a line that outruns the claim is left for the verifier to report.
+        if node.is_terminal() || plies_left == 0 {
+            return;
+        }
+
+        let mut buffer = Vec::with_capacity(node.action_space_size());
+        node.generate_moves(&mut buffer);
+
+        if node.turn() != self.winner.sign() {
+            for m in buffer {
+                node.push(m);
+                self.extend(node, plies_left - 1, budgets);
+                node.pop(m);
+            }
+            return;
+        }
+
+        if budgets.get(&node.hashkey()).is_some_and(|&budget| budget <= plies_left) {
+            return;
+        }
+
+        // a loss for the loser within the remaining budget means
+        // every line from the child ends in a win in time.
+        let m = buffer
+            .into_iter()
+            .find(|&m| {
+                node.push(m);
+                let value = negamax(node, plies_left - 1, -INF, INF, &mut SearchContext::new());
+                node.pop(m);
+                value.is_loss()
+            })
+            .expect("the solver's claimed win could not be reproduced");
+
+        self.strategy.insert(node.hashkey(), m.to_string());
+        budgets.insert(node.hashkey(), plies_left);
+        node.push(m);
+        self.extend(node, plies_left - 1, budgets);
+        node.pop(m);
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l.trim()));
+        let err = |line, message: &str| ParseError { line, message: message.to_string() };
+
+        let (line, winner) = lines.next().ok_or_else(|| err(1, "missing winner"))?;
+        let winner = match winner {
+            "winner X" => Player::X,
+            "winner O" => Player::O,
+            _ => return Err(err(line, "expected \"winner X\" or \"winner O\"")),
+        };
+        let (line, plies) = lines.next().ok_or_else(|| err(2, "missing plies"))?;
+        let plies = plies
+            .strip_prefix("plies ")
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(|| err(line, "expected \"plies <n>\""))?;
+
+        let mut strategy = HashMap::new();
+        for (line, text) in lines.filter(|(_, l)| !l.is_empty()) {
+            let (key, notation) = text
+                .split_once(' ')
+                .ok_or_else(|| err(line, "expected \"<key> <move>\""))?;
+            let key = u64::from_str_radix(key, 16)
+                .map_err(|_| err(line, "invalid position key"))?;
+            strategy.insert(key, notation.to_string());
+        }
+
+        Ok(Self { winner, plies, strategy })
+    }
+}
+
+impl Display for Certificate {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "winner {}", self.winner)?;
+        writeln!(f, "plies {}", self.plies)?;
+        // sort the entries so that exports are reproducible.
+        let mut entries = self.strategy.iter().collect::<Vec<_>>();
+        entries.sort();
+        for (key, notation) in entries {
+            writeln!(f, "{:016x} {}", key, notation)?;
+        }
+        Ok(())
+    }
+}
+
+// checks a certificate against the game rules alone: no search is done,
+// so a passing certificate does not depend on the correctness of the solver.
+// returns the length of the longest line of play on success.
+pub fn verify<G: Keyed>(game: G, cert: &Certificate) -> Result<usize, VerifyError> {
+    let mut game = game;
+    let actual = verify_internal(&mut game, cert, cert.plies)?;
+    if actual != cert.plies {
+        return Err(VerifyError::DepthMismatch { claimed: cert.plies, actual });
+    }
+    Ok(actual)
+}
+
+fn verify_internal<G: Keyed>(node: &mut G, cert: &Certificate, plies_left: usize) -> Result<usize, VerifyError> {
+    let outcome = node.outcome();
+    let mut buffer = Vec::with_capacity(node.action_space_size());
+    node.generate_moves(&mut buffer);
+
+    // a position with no moves is over, even if the game doesn't say so.
+    if outcome.is_some() || buffer.is_empty() {
+        if outcome.and_then(Outcome::winner) == Some(cert.winner) {
+            return Ok(0);
+        }
+        return Err(VerifyError::WrongResult { key: node.hashkey(), outcome });
+    }
+
+    if plies_left == 0 {
+        return Err(VerifyError::TooLong { key: node.hashkey() });
+    }
+
+    if node.turn() == cert.winner.sign() {
+        let key = node.hashkey();
+        let notation = cert.strategy.get(&key).ok_or(VerifyError::MissingMove { key })?;
+        let m = buffer
+            .into_iter()
+            .find(|m| m.to_string() == *notation)
+            .ok_or_else(|| VerifyError::IllegalMove { key, notation: notation.clone() })?;
+        node.push(m);
+        let depth = verify_internal(node, cert, plies_left - 1);
+        node.pop(m);
+        return Ok(depth? + 1);
+    }
+
+    let mut longest = 0;
+    for m in buffer {
+        node.push(m);
+        let depth = verify_internal(node, cert, plies_left - 1);
+        node.pop(m);
+        longest = std::cmp::max(longest, depth? + 1);
+    }
+    Ok(longest)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::coverttt::{CoverTTT, CoverTTTMove, Size};
+    use crate::game::{Game, Keyed, Player};
+    use crate::tictactoe::{TicTacToe, TicTacToeMove};
+
+    use super::{verify, Certificate, VerifyError};
+
+    fn ttt_xwin() -> TicTacToe {
+        let mut root = TicTacToe::new();
+        root.push(TicTacToeMove::new(4));
+        root.push(TicTacToeMove::new(1));
+        root
+    }
+
+    #[test]
+    fn ttt_draw_has_no_certificate() {
+        assert!(Certificate::generate(TicTacToe::new()).is_none());
+    }
+
+    #[test]
+    fn ttt_xwin_verifies() {
+        let root = ttt_xwin();
+        let cert = Certificate::generate(root).unwrap();
+        assert_eq!(cert.winner, Player::X);
+        assert_eq!(cert.plies, 5);
+        assert_eq!(verify(root, &cert), Ok(5));
+    }
+
+    #[test]
+    fn ttt_owin_verifies() {
+        let mut root = TicTacToe::new();
+        root.push(TicTacToeMove::new(1));
+        root.push(TicTacToeMove::new(4));
+        root.push(TicTacToeMove::new(7));
+        let cert = Certificate::generate(root).unwrap();
+        assert_eq!(cert.winner, Player::O);
+        assert_eq!(verify(root, &cert), Ok(5));
+    }
+
+    #[test]
+    fn cttt_xwin_verifies() {
+        let mut root = CoverTTT::new();
+        root.push(CoverTTTMove::new(4, Size::Big));
+        root.push(CoverTTTMove::new(1, Size::Small));
+        let cert = Certificate::generate(root).unwrap();
+        assert_eq!(verify(root, &cert), Ok(5));
+    }
+
+    #[test]
+    fn budgets_are_kept_apart() {
+        let root = ttt_xwin();
+        let mut cert = Certificate { winner: Player::X, plies: 5, strategy: HashMap::new() };
+        let mut budgets = HashMap::new();
+        // with two plies to spare, X can settle on a slower win. reaching
+        // the same position again with the tight budget has to replace it.
+        cert.extend(&mut root.clone(), 7, &mut budgets);
+        assert!(verify(root, &cert).is_err());
+        cert.extend(&mut root.clone(), 5, &mut budgets);
+        assert_eq!(verify(root, &cert), Ok(5));
+
+        // a position reached with no plies left is left to the verifier.
+        cert.extend(&mut TicTacToe::new(), 0, &mut budgets);
+        assert!(!cert.strategy.contains_key(&TicTacToe::new().hashkey()));
+    }
+
+    #[test]
+    fn export_roundtrip() {
+        let root = ttt_xwin();
+        let cert = Certificate::generate(root).unwrap();
+        let parsed = Certificate::parse(&cert.to_string()).unwrap();
+        assert_eq!(parsed, cert);
+        assert_eq!(verify(root, &parsed), Ok(5));
+    }
+
+    #[test]
+    fn tampered_certificates_fail() {
+        let root = ttt_xwin();
+        let cert = Certificate::generate(root).unwrap();
+
+        let mut wrong_depth = cert.clone();
+        wrong_depth.plies = 3;
+        assert!(matches!(verify(root, &wrong_depth), Err(VerifyError::TooLong { .. })));
+
+        let mut too_generous = cert.clone();
+        too_generous.plies = 7;
+        assert_eq!(verify(root, &too_generous), Err(VerifyError::DepthMismatch { claimed: 7, actual: 5 }));
+
+        let mut wrong_winner = cert.clone();
+        wrong_winner.winner = Player::O;
+        assert!(verify(root, &wrong_winner).is_err());
+
+        let mut missing = cert.clone();
+        missing.strategy.remove(&root.hashkey());
+        assert_eq!(verify(root, &missing), Err(VerifyError::MissingMove { key: root.hashkey() }));
+
+        let mut illegal = cert;
+        illegal.strategy.insert(root.hashkey(), "4".to_string());
+        assert!(matches!(verify(root, &illegal), Err(VerifyError::IllegalMove { .. })));
+    }
+}
@@ -0,0 +1,164 @@
+use rand::Rng;
+
+// a source of chance, such as a set of dice, as a distribution over
+// numbered outcomes. games of chance take their probabilities from one of
+// these for search, and sample from the same model when playing, so the
+// two can never disagree.
+pub trait ChanceModel {
+    // every possible outcome with its probability. the probabilities sum to one.
+    fn outcomes(&self) -> Vec<(usize, f64)>;
+
+    // the largest outcome, which bounds the moves a game needs for them.
+    fn max_outcome(&self) -> usize {
+        self.outcomes().iter().map(|&(outcome, _)| outcome).max().unwrap_or(0)
+    }
+
+    // draws an outcome, with the probabilities given by outcomes().
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        let outcomes = self.outcomes();
+        let mut x = rng.gen::<f64>();
+        for &(outcome, prob) in &outcomes {
+            if x < prob {
+                return outcome;
+            }
+            x -= prob;
+        }
+        // the probabilities may sum to a hair under one.
+        outcomes.last().expect("a chance model has outcomes").0
+    }
+
+    // the expected value of f over the outcomes.
+    fn expectation(&self, f: impl Fn(usize) -> f64) -> f64 {
+        self.outcomes().into_iter().map(|(outcome, prob)| prob * f(outcome)).sum()
+    }
+}
+
+// the dice that ship with the games. every probability is a ratio of
+// integers, and so exact when the denominator is a power of two.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Dice {
+    // the number of marked faces showing on this many two-sided dice,
+    // like the four tetrahedral dice of Ur.
+    Binary(u32),
+    // a fair die numbered from one to this many sides, like a d6.
+    Die(u32),
+    // dice that favour some rolls, for testing. built by Dice::loaded.
+    Loaded(LoadedDice),
+}
+
+// outcome i comes up with probability weights[i] / sum(weights). the
+// weights are private, so that every set has passed the check in
+// Dice::loaded that some roll can come up.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LoadedDice {
+    weights: Vec<u32>,
+}
+
+impl Dice {
+    pub fn loaded(weights: Vec<u32>) -> Self {
+        assert!(weights.iter().any(|&w| w > 0), "loaded dice need a roll with a weight above zero");
+        Dice::Loaded(LoadedDice { weights })
+    }
+}
+
+impl ChanceModel for Dice {
+    fn outcomes(&self) -> Vec<(usize, f64)> {
+        match self {
+            Dice::Binary(count) => {
+                let total = 2f64.powi(*count as i32);
+                let mut ways = 1u64;
+                (0..=*count as u64)
+                    .map(|k| {
+                        let prob = ways as f64 / total;
+                        // C(n, k + 1) from C(n, k).
+                        ways = ways * (*count as u64 - k) / (k + 1);
+                        (k as usize, prob)
+                    })
+                    .collect()
+            },
+            Dice::Die(sides) => (1..=*sides as usize).map(|side| (side, 1.0 / *sides as f64)).collect(),
+            Dice::Loaded(LoadedDice { weights }) => {
+                let total = weights.iter().map(|&w| w as f64).sum::<f64>();
+                weights
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &w)| w > 0)
+                    .map(|(outcome, &w)| (outcome, w as f64 / total))
+                    .collect()
+            },
+        }
+    }
+
+    fn max_outcome(&self) -> usize {
+        match self {
+            Dice::Binary(count) => *count as usize,
+            Dice::Die(sides) => *sides as usize,
+            Dice::Loaded(LoadedDice { weights }) => weights.iter().rposition(|&w| w > 0).unwrap_or(0),
+        }
+    }
+
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        match self {
+            // roll the dice themselves, as a player would.
+            Dice::Binary(count) => (0..*count).filter(|_| rng.gen_bool(0.5)).count(),
+            Dice::Die(sides) => rng.gen_range(1..=*sides as usize),
+            Dice::Loaded(LoadedDice { weights }) => {
+                let total = weights.iter().map(|&w| w as u64).sum::<u64>();
+                let mut x = rng.gen_range(0..total);
+                for (outcome, &w) in weights.iter().enumerate() {
+                    if x < w as u64 {
+                        return outcome;
+                    }
+                    x -= w as u64;
+                }
+                unreachable!()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{ChanceModel, Dice};
+
+    #[test]
+    fn exact_probabilities() {
+        assert_eq!(Dice::Binary(4).outcomes(), vec![(0, 0.0625), (1, 0.25), (2, 0.375), (3, 0.25), (4, 0.0625)]);
+        assert_eq!(Dice::Binary(3).outcomes(), vec![(0, 0.125), (1, 0.375), (2, 0.375), (3, 0.125)]);
+        assert_eq!(Dice::loaded(vec![1, 0, 3]).outcomes(), vec![(0, 0.25), (2, 0.75)]);
+        assert_eq!(Dice::Die(6).max_outcome(), 6);
+        assert_eq!(Dice::Binary(4).max_outcome(), 4);
+        assert_eq!(Dice::loaded(vec![1, 3, 0]).max_outcome(), 1);
+        assert_eq!(Dice::Binary(4).expectation(|roll| roll as f64), 2.0);
+        assert!((Dice::Die(6).expectation(|roll| roll as f64) - 3.5).abs() < 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "weight above zero")]
+    fn loaded_dice_need_a_roll() {
+        Dice::loaded(vec![0, 0]);
+    }
+
+    #[test]
+    fn sampling_matches_probabilities() {
+        const SAMPLES: usize = 40_000;
+        let mut rng = StdRng::seed_from_u64(5);
+        for dice in [Dice::Binary(4), Dice::Binary(3), Dice::Die(6), Dice::loaded(vec![1, 0, 3])] {
+            let mut counts = vec![0; dice.max_outcome() + 1];
+            for _ in 0..SAMPLES {
+                counts[dice.sample(&mut rng)] += 1;
+            }
+            for (outcome, prob) in dice.outcomes() {
+                let freq = counts[outcome] as f64 / SAMPLES as f64;
+                // a little over four standard deviations at these sample sizes.
+                assert!((freq - prob).abs() < 0.011, "{:?} rolled {} too often or too rarely", dice, outcome);
+            }
+            let impossible = (0..counts.len()).filter(|&o| dice.outcomes().iter().all(|&(x, _)| x != o));
+            for outcome in impossible {
+                assert_eq!(counts[outcome], 0);
+            }
+        }
+    }
+}
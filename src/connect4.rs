@@ -1,12 +1,12 @@
-#![allow(clippy::unusual_byte_groupings)]
-
 use std::fmt::Display;
 
-use crate::game::Game;
+use crate::{game::{Game, Keyed, Outcome, Player, ToMove}, tensor::TensorEncode};
 
 const WIDTH: usize = 7;
 const HEIGHT: usize = 6;
-const ROW_LENGTH: usize = 4;
+// each column takes HEIGHT bits and a spare bit above them,
+// so that the stones of one column never carry into the next.
+const COLUMN_BITS: usize = HEIGHT + 1;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Connect4 {
@@ -17,12 +17,12 @@ pub struct Connect4 {
     //    * 3 10 17 24 31 38 45
     //    * 2  9 16 23 30 37 44
     //    * 1  8 15 22 29 36 43
-    //    * 0  7 14 21 28 35 42 
-    // 
+    //    * 0  7 14 21 28 35 42
+    //
     // Position is stored as
     // - a bitboard "filled" with 1 on any color stones
     // - a bitboard "current" with 1 on stones of current player
-    
+
     filled: u64,
     // "board" has ones for the current player's pieces
     // and zeros for the opponent's pieces, flipped every turn
@@ -41,62 +41,58 @@ impl Connect4 {
         }
     }
 
-    fn is_won(&self) -> bool {
-        let mask = 1u64 << self.last_played_loc;
+    // whether the stones have four in a row in any direction.
+    fn has_four(stones: u64) -> bool {
+        // vertically, horizontally, and along both diagonals.
+        const SHIFTS: [usize; 4] = [1, COLUMN_BITS, COLUMN_BITS + 1, COLUMN_BITS - 1];
+        SHIFTS.iter().any(|&shift| {
+            let pairs = stones & (stones >> shift);
+            pairs & (pairs >> (2 * shift)) != 0
+        })
+    }
 
-        for dir in 0..4 {
-            if self.win_dir(mask, dir) {
-                return true;
-            }
-        }
+    // the stones of the player who moved last.
+    fn last_mover_stones(&self) -> u64 {
+        self.current ^ self.filled
+    }
 
-        false
-    }
-
-    fn win_dir(&self, mask: u64, dir: usize) -> bool {
-        const SHIFTS: [usize; 4] = [1, WIDTH, WIDTH + 1, WIDTH - 1];
-        let shift = SHIFTS[dir];
-        let board = self.current;
-        let starting_row = Self::row(mask);
-        // MUST TRACK: shifts that go past the edge.
-        let mut on_count = 0;
-
-        let mut mut_mask = mask;
-        while on_count != 4
-            && (board & mut_mask) != 0
-            && mut_mask != 0
-            && Self::row(mut_mask) == starting_row
-        {
-            on_count += 1;
-            mut_mask <<= shift;
-        }
-        let mut mut_mask = mask >> shift;
-        while on_count != 4
-            && (board & mut_mask) != 0
-            && mut_mask != 0
-            && Self::row(mut_mask) == starting_row
-        {
-            on_count += 1;
-            mut_mask >>= shift;
-        }
-        
-        on_count == ROW_LENGTH
+    fn bottom_mask(col: usize) -> u64 {
+        1u64 << (col * COLUMN_BITS)
     }
 
-    #[inline(always)]
-    fn row(mask: u64) -> usize {
-        (mask.trailing_zeros() / WIDTH as u32) as usize
+    fn top_mask(col: usize) -> u64 {
+        1u64 << (col * COLUMN_BITS + HEIGHT - 1)
     }
 
-    fn bottom_mask(col: usize) -> u64 {
-        1u64 << (col * (HEIGHT + 1))
+    fn column_mask(col: usize) -> u64 {
+        ((1u64 << HEIGHT) - 1) << (col * COLUMN_BITS)
+    }
+
+    fn bit(row: usize, col: usize) -> u64 {
+        1u64 << (col * COLUMN_BITS + row)
+    }
+
+    // the player whose stone is at the given row, counting up from the
+    // bottom, and column, if there is one.
+    fn stone_at(&self, row: usize, col: usize) -> Option<Player> {
+        let bit = Self::bit(row, col);
+        if self.filled & bit == 0 {
+            return None;
+        }
+        let to_move = if self.moves & 1 == 0 { Player::X } else { Player::O };
+        if self.current & bit != 0 {
+            Some(to_move)
+        } else {
+            Some(to_move.opponent())
+        }
     }
 }
 
 impl Game for Connect4 {
+    // the column to drop a stone into.
     type Move = usize;
 
-    fn turn(&self) -> i32 {
+    fn turn(&self) -> i8 {
         if self.moves & 1 == 0 {
             1
         } else {
@@ -104,29 +100,32 @@ impl Game for Connect4 {
         }
     }
 
-    fn evaluate(&self) -> i32 {
-        if self.is_won() {
-            return -self.turn();
+    fn to_move(&self) -> ToMove {
+        if self.moves & 1 == 0 {
+            ToMove::Max
+        } else {
+            ToMove::Min
         }
-
-        0
     }
 
-    fn is_terminal(&self) -> bool {
-        self.moves == WIDTH * HEIGHT || self.evaluate() != 0
-    }
-
-    fn generate_legal_moves(&self, buffer: &mut Vec<Self::Move>) {
-        let filled = self.filled;
-        let mut empty = !filled & 0b1111111;
-        while empty != 0 {
-            buffer.push(empty.trailing_zeros() as usize);
-            empty &= empty - 1; // clear the least significant bit set
+    fn outcome(&self) -> Option<Outcome> {
+        if Self::has_four(self.last_mover_stones()) {
+            // only the player who just moved can have made a line.
+            let last_mover = if self.moves & 1 == 0 { Player::O } else { Player::X };
+            Some(Outcome::Win(last_mover))
+        } else if self.moves == WIDTH * HEIGHT {
+            Some(Outcome::Draw)
+        } else {
+            None
         }
     }
 
-    fn action_space_size(&self) -> usize {
-        WIDTH
+    fn generate_moves(&self, buffer: &mut Vec<Self::Move>) {
+        for col in 0..WIDTH {
+            if self.filled & Self::top_mask(col) == 0 {
+                buffer.push(col);
+            }
+        }
     }
 
     fn push(&mut self, m: Self::Move) {
@@ -137,21 +136,67 @@ impl Game for Connect4 {
     }
 
     fn pop(&mut self, m: Self::Move) {
-        todo!()
+        // the highest stone in the column is the one to take back.
+        let column = self.filled & Self::column_mask(m);
+        let top = 1u64 << (63 - column.leading_zeros());
+        self.filled ^= top;
+        self.current ^= self.filled;
+        self.moves -= 1;
+    }
+
+    fn action_space_size(&self) -> usize {
+        WIDTH
+    }
+
+    fn move_index(&self, m: Self::Move) -> usize {
+        m
+    }
+
+    fn order_moves(&self, buffer: &mut [Self::Move]) {
+        // central columns are on more lines.
+        buffer.sort_by_key(|&col| col.abs_diff(WIDTH / 2));
+    }
+}
+
+impl Keyed for Connect4 {
+    fn hashkey(&self) -> u64 {
+        // the spare bit above each column's stones marks its height,
+        // which with the stones of the side to move gives the position.
+        let heights = self.filled + (0..WIDTH).map(Self::bottom_mask).sum::<u64>();
+        self.current + heights
+    }
+}
+
+impl TensorEncode for Connect4 {
+    fn tensor_shape(&self) -> Vec<usize> {
+        vec![2, HEIGHT, WIDTH]
+    }
+
+    fn encode_tensor(&self) -> Vec<f32> {
+        // a plane for the side to move's stones, then one for the
+        // opponent's, with the rows counted up from the bottom.
+        let planes = [self.current, self.last_mover_stones()];
+        planes
+            .iter()
+            .flat_map(|&plane| {
+                (0..HEIGHT).flat_map(move |row| (0..WIDTH).map(move |col| (plane & Self::bit(row, col) != 0) as u8 as f32))
+            })
+            .collect()
+    }
+
+    fn action_to_move(&self, index: usize) -> Option<Self::Move> {
+        (index < WIDTH && self.filled & Self::top_mask(index) == 0).then_some(index)
     }
 }
 
 impl Display for Connect4 {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        for row in 0..HEIGHT {
+        for row in (0..HEIGHT).rev() {
             for col in 0..WIDTH {
-                let mask = 1u64 << (row * WIDTH + col);
-                if (self.current[0] & mask) != 0 {
-                    write!(f, "X")?;
-                } else if (self.current[1] & mask) != 0 {
-                    write!(f, "O")?;
-                } else {
-                    write!(f, ".")?;
+                match self.stone_at(row, col) {
+                    Some(Player::X) => write!(f, "X")?,
+                    Some(Player::O) => write!(f, "O")?,
+                    None => write!(f, ".")?,
                 }
             }
             writeln!(f)?;
@@ -162,17 +207,99 @@ impl Display for Connect4 {
 
 #[cfg(test)]
 mod tests {
+    use crate::game::{Game, Outcome, Player};
+    use crate::perft::perft;
+
     use super::*;
 
     #[test]
-    fn row_correct() {
-        let bitboard = 1u64 << 0;
-        assert_eq!(0, Connect4::row(bitboard));
-        let bitboard = 1u64 << (WIDTH - 1);
-        assert_eq!(0, Connect4::row(bitboard));
-        let bitboard = 1u64 << WIDTH;
-        assert_eq!(1, Connect4::row(bitboard));
-        let bitboard = 1u64 << (WIDTH * HEIGHT - 1);
-        assert_eq!(5, Connect4::row(bitboard));
+    fn bit_layout() {
+        assert_eq!(Connect4::bit(0, 0), 1u64 << 0);
+        assert_eq!(Connect4::bit(HEIGHT - 1, 0), 1u64 << 5);
+        assert_eq!(Connect4::bit(0, 1), 1u64 << 7);
+        assert_eq!(Connect4::bit(HEIGHT - 1, WIDTH - 1), 1u64 << 47);
+        assert_eq!(Connect4::top_mask(2), Connect4::bit(HEIGHT - 1, 2));
+    }
+
+    #[test]
+    fn perft_counts() {
+        let mut board = Connect4::new();
+        assert_eq!(perft(&mut board, 1), 7);
+        assert_eq!(perft(&mut board, 2), 49);
+        assert_eq!(perft(&mut board, 4), 2401);
+        assert_eq!(board, Connect4::new());
+    }
+
+    #[test]
+    fn wins_and_full_columns() {
+        // X builds a row along the bottom while O stacks on column 6.
+        let mut board = Connect4::new();
+        for m in [0, 6, 1, 6, 2, 6] {
+            board.push(m);
+            assert_eq!(board.outcome(), None);
+        }
+        board.push(3);
+        assert_eq!(board.outcome(), Some(Outcome::Win(Player::X)));
+        assert_eq!(board.to_string().lines().last(), Some("XXXX..O"));
+        board.pop(3);
+        // and O finishes the column off vertically.
+        board.push(5);
+        board.push(6);
+        assert_eq!(board.outcome(), Some(Outcome::Win(Player::O)));
+
+        let mut board = Connect4::new();
+        for _ in 0..HEIGHT {
+            board.push(0);
+        }
+        let mut moves = Vec::new();
+        board.generate_moves(&mut moves);
+        assert_eq!(moves, vec![1, 2, 3, 4, 5, 6]);
+        // columns filled alternately have no vertical four.
+        assert_eq!(board.outcome(), None);
+    }
+
+    #[test]
+    fn pop_takes_back_the_top_stone() {
+        let mut board = Connect4::new();
+        for m in [3, 3, 4] {
+            board.push(m);
+        }
+        let before = board;
+        board.push(3);
+        board.pop(3);
+        assert_eq!(board, before);
+        assert_eq!(board.to_string().lines().rev().take(2).collect::<Vec<_>>(), vec!["...XX..", "...O..."]);
+    }
+
+    #[test]
+    fn transpositions_share_a_key() {
+        let mut a = Connect4::new();
+        let mut b = Connect4::new();
+        for m in [0, 1, 2, 3] {
+            a.push(m);
+        }
+        for m in [2, 3, 0, 1] {
+            b.push(m);
+        }
+        assert_eq!(a.hashkey(), b.hashkey());
+        // the same stones with the colours swapped are a different position.
+        let mut c = Connect4::new();
+        for m in [1, 0, 3, 2] {
+            c.push(m);
+        }
+        assert_ne!(a.hashkey(), c.hashkey());
+        // as is an empty column against one with a stone in it.
+        a.push(4);
+        b.push(5);
+        assert_ne!(a.hashkey(), b.hashkey());
+    }
+
+    #[test]
+    fn diagonal() {
+        let mut board = Connect4::new();
+        for m in [0, 1, 1, 2, 2, 3, 2, 3, 3, 6, 3] {
+            board.push(m);
+        }
+        assert_eq!(board.outcome(), Some(Outcome::Win(Player::X)));
     }
 }
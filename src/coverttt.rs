@@ -2,7 +2,7 @@
 
 use std::fmt::Display;
 
-use crate::game::{Game, ToMove, Keyed};
+use crate::{game::{Game, ToMove, Keyed, Outcome, Player}, tensor::TensorEncode};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct CoverTTT {
@@ -121,6 +121,41 @@ impl CoverTTT {
         }
     }
 
+    fn last_mover(&self) -> Player {
+        if self.moves & 1 == 0 {
+            Player::O
+        } else {
+            Player::X
+        }
+    }
+
+    fn has_line(&self) -> bool {
+        // check first diagonal
+        if self.probe_spot(0) && self.probe_spot(4) && self.probe_spot(8) {
+            return true;
+        }
+
+        // check second diagonal
+        if self.probe_spot(2) && self.probe_spot(4) && self.probe_spot(6) {
+            return true;
+        }
+
+        // check rows
+        for i in 0..3 {
+            if self.probe_spot(i * 3) && self.probe_spot(i * 3 + 1) && self.probe_spot(i * 3 + 2) {
+                return true;
+            }
+        }
+        // check columns
+        for i in 0..3 {
+            if self.probe_spot(i) && self.probe_spot(i + 3) && self.probe_spot(i + 6) {
+                return true;
+            }
+        }
+
+        false
+    }
+
     fn layer_bitmask(&self, size: Size) -> u16 {
         match size {
             Size::Big => self.board[0] | self.board[1],
@@ -177,39 +212,19 @@ impl Game for CoverTTT {
         }
     }
 
-    fn evaluate(&self) -> i8 {
-        // check first diagonal
-        if self.probe_spot(0) && self.probe_spot(4) && self.probe_spot(8) {
-            return -self.turn();
-        }
-
-        // check second diagonal
-        if self.probe_spot(2) && self.probe_spot(4) && self.probe_spot(6) {
-            return -self.turn();
-        }
-
-        // check rows
-        for i in 0..3 {
-            if self.probe_spot(i * 3) && self.probe_spot(i * 3 + 1) && self.probe_spot(i * 3 + 2) {
-                return -self.turn();
-            }
-        }
-        // check columns
-        for i in 0..3 {
-            if self.probe_spot(i) && self.probe_spot(i + 3) && self.probe_spot(i + 6) {
-                return -self.turn();
-            }
-        }
-
-        0
-    }
-
-    fn is_terminal(&self) -> bool {
-        (self.layer_bitmask(Size::Small) == 6
+    fn outcome(&self) -> Option<Outcome> {
+        if self.has_line() {
+            // only the player who just moved can have made a line.
+            Some(Outcome::Win(self.last_mover()))
+        } else if (self.layer_bitmask(Size::Small) == 6
             && self.layer_bitmask(Size::Medium) == 6
             && self.layer_bitmask(Size::Big) == 6)
-            || self.evaluate() != 0
             || !self.legal_moves_exist()
+        {
+            Some(Outcome::Draw)
+        } else {
+            None
+        }
     }
 
     fn generate_moves(&self, buffer: &mut Vec<Self::Move>) {
@@ -264,6 +279,17 @@ impl Game for CoverTTT {
     fn action_space_size(&self) -> usize {
         9 * 3
     }
+
+    fn move_index(&self, m: Self::Move) -> usize {
+        m.size as usize / 2 * 9 + m.idx
+    }
+
+    fn order_moves(&self, buffer: &mut [Self::Move]) {
+        // prefer squares on more lines, and then bigger pieces,
+        // as they can't be covered.
+        const LINES_THROUGH: [u8; 9] = [3, 2, 3, 2, 4, 2, 3, 2, 3];
+        buffer.sort_by_key(|m| (std::cmp::Reverse(LINES_THROUGH[m.idx]), m.size as u8));
+    }
 }
 
 impl Keyed for CoverTTT {
@@ -277,6 +303,22 @@ impl Keyed for CoverTTT {
     }
 }
 
+impl TensorEncode for CoverTTT {
+    fn tensor_shape(&self) -> Vec<usize> {
+        vec![6, 3, 3]
+    }
+
+    fn encode_tensor(&self) -> Vec<f32> {
+        // for each size from big to small, a plane for the side to move's
+        // pieces and then one for the opponent's, covered or not.
+        let us = self.moves & 1;
+        (0..3)
+            .flat_map(|size| [self.board[2 * size + us], self.board[2 * size + (us ^ 1)]])
+            .flat_map(|plane| (0..9).map(move |i| (plane >> i & 1) as f32))
+            .collect()
+    }
+}
+
 impl Display for CoverTTT {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for x in 0..3 {
@@ -0,0 +1,378 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    game::{Keyed, StochasticGame, ToMove},
+    tensor::TensorEncode,
+    winprob::{favourite_move, sample_move, Policy},
+};
+
+// training data for machine learning, from games that an agent plays against
+// itself. every position where a player moves becomes a sample: the encoded
+// position, how the agent weighed the moves there, and how the game ended.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    // the position, from TensorEncode::encode_tensor().
+    pub encoding: Vec<f32>,
+    // the agent's distribution over the action indices. illegal moves are zero.
+    pub policy: Vec<f32>,
+    // how the game ended for the side to move: 1 for a win, -1 for a loss,
+    // and 0 for a draw or a game that got stuck.
+    pub outcome: f32,
+    // the number of moves played before this position, chance moves included.
+    pub ply: u32,
+    pub key: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // one JSON object per line, for reading anywhere.
+    JsonLines,
+    // little-endian numbers after a header, for reading fast. see write_binary.
+    Binary,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::JsonLines => "jsonl",
+            Format::Binary => "bin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatasetConfig {
+    pub games: usize,
+    pub seed: u64,
+    // for this many plies at the start of each game, moves are drawn from the
+    // agent's distribution, so that the games differ. after that the agent
+    // plays the move it favours most.
+    pub sample_plies: usize,
+    // keep only the first sample of each position, as told by its hashkey.
+    pub dedup: bool,
+    // the most samples written to one file. None writes them all to one.
+    pub shard_size: Option<usize>,
+    pub format: Format,
+}
+
+impl DatasetConfig {
+    pub fn new(games: usize, seed: u64) -> Self {
+        Self {
+            games,
+            seed,
+            sample_plies: 4,
+            dedup: false,
+            shard_size: None,
+            format: Format::JsonLines,
+        }
+    }
+
+    pub fn with_sample_plies(self, sample_plies: usize) -> Self {
+        Self {
+            sample_plies,
+            ..self
+        }
+    }
+
+    pub fn with_dedup(self) -> Self {
+        Self {
+            dedup: true,
+            ..self
+        }
+    }
+
+    pub fn with_shard_size(self, shard_size: usize) -> Self {
+        assert!(shard_size > 0, "a shard needs at least one sample");
+        Self {
+            shard_size: Some(shard_size),
+            ..self
+        }
+    }
+
+    pub fn with_format(self, format: Format) -> Self {
+        Self {
+            format,
+            ..self
+        }
+    }
+}
+
+// plays the games of a game without chance. each game is seeded from
+// config.seed, so the same agent and config give the same samples.
+pub fn generate<G, P>(game: &G, policy: &mut P, config: DatasetConfig) -> Vec<Sample>
+where
+    G: TensorEncode + Keyed,
+    P: Policy<G>,
+{
+    generate_by(game, policy, config, |_, _| panic!("a chance node in a game without chance"))
+}
+
+// the same, for a game of chance, with the dice rolled from the game's seed.
+// chance nodes aren't samples.
+pub fn generate_with_chance<G, P>(game: &G, policy: &mut P, config: DatasetConfig) -> Vec<Sample>
+where
+    G: StochasticGame + TensorEncode + Keyed,
+    P: Policy<G>,
+{
+    generate_by(game, policy, config, |game, rng| game.sample_chance_move(rng))
+}
+
+fn generate_by<G, P, C>(game: &G, policy: &mut P, config: DatasetConfig, chance: C) -> Vec<Sample>
+where
+    G: TensorEncode + Keyed,
+    P: Policy<G>,
+    C: Fn(&G, &mut StdRng) -> G::Move,
+{
+    let mut seeds = StdRng::seed_from_u64(config.seed);
+    let mut seen = HashSet::new();
+    let mut samples = Vec::new();
+    let mut moves = Vec::with_capacity(game.action_space_size());
+    for _ in 0..config.games {
+        let mut rng = StdRng::seed_from_u64(seeds.gen());
+        let mut game = game.clone();
+        // the samples of this game, with the side to move at each.
+        let mut positions = Vec::new();
+        let mut ply = 0;
+        while !game.is_terminal() {
+            let m = match game.to_move() {
+                ToMove::Chance => chance(&game, &mut rng),
+                ToMove::Max | ToMove::Min => {
+                    moves.clear();
+                    game.generate_moves(&mut moves);
+                    if moves.is_empty() {
+                        break;
+                    }
+                    let distribution = policy.distribution(&game, &moves, &mut rng);
+                    let key = game.hashkey();
+                    if !config.dedup || seen.insert(key) {
+                        let mut policy = vec![0.0; game.action_space_size()];
+                        for (&m, &p) in moves.iter().zip(&distribution) {
+                            policy[game.action_index(m)] = p as f32;
+                        }
+                        let sample = Sample { encoding: game.encode_tensor(), policy, outcome: 0.0, ply, key };
+                        positions.push((sample, game.turn()));
+                    }
+                    if (ply as usize) < config.sample_plies {
+                        sample_move(&moves, &distribution, &mut rng)
+                    } else {
+                        favourite_move(&moves, &distribution, &mut rng)
+                    }
+                },
+            };
+            policy.observe(&game, m);
+            game.push(m);
+            ply += 1;
+        }
+        let x_result = game.outcome().map_or(0, |outcome| outcome.sign());
+        samples.extend(positions.into_iter().map(|(sample, turn)| Sample { outcome: (x_result * turn) as f32, ..sample }));
+    }
+    samples
+}
+
+// writes the samples to files named after the prefix, as `<prefix>.jsonl`, or
+// as `<prefix>-000.jsonl`, `<prefix>-001.jsonl` and so on if the config splits
+// them into shards, and returns the paths written.
+pub fn write(samples: &[Sample], shape: &[usize], prefix: &str, config: DatasetConfig) -> io::Result<Vec<PathBuf>> {
+    let extension = config.format.extension();
+    let shards = match config.shard_size {
+        Some(size) => samples.chunks(size).enumerate().map(|(i, shard)| (PathBuf::from(format!("{}-{:03}.{}", prefix, i, extension)), shard)).collect(),
+        None => vec![(PathBuf::from(format!("{}.{}", prefix, extension)), samples)],
+    };
+    let mut paths = Vec::new();
+    for (path, shard) in shards {
+        let mut out = BufWriter::new(File::create(&path)?);
+        match config.format {
+            Format::JsonLines => write_json_lines(shard, shape, &mut out)?,
+            Format::Binary => write_binary(shard, shape, &mut out)?,
+        }
+        out.flush()?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+fn json_array<T: ToString>(values: &[T]) -> String {
+    format!("[{}]", values.iter().map(T::to_string).collect::<Vec<_>>().join(","))
+}
+
+// one line per sample, such as
+// {"ply":0,"key":"0x0","outcome":-1,"shape":[2,3,3],"encoding":[0,...],"policy":[0.1,...]}
+// the key is a hex string, as a JSON number can't hold every u64 exactly.
+pub fn write_json_lines<W: Write>(samples: &[Sample], shape: &[usize], out: &mut W) -> io::Result<()> {
+    let shape = json_array(shape);
+    for sample in samples {
+        writeln!(
+            out,
+            "{{\"ply\":{},\"key\":\"{:#x}\",\"outcome\":{},\"shape\":{},\"encoding\":{},\"policy\":{}}}",
+            sample.ply,
+            sample.key,
+            sample.outcome,
+            shape,
+            json_array(&sample.encoding),
+            json_array(&sample.policy)
+        )?;
+    }
+    Ok(())
+}
+
+const MAGIC: &[u8; 4] = b"SPDS";
+const VERSION: u32 = 1;
+
+// the magic bytes "SPDS", then as little-endian u32s the version, the number
+// of dimensions in the shape, each dimension, the number of actions and the
+// number of samples. each sample follows as its key (u64), ply (u32), outcome
+// (f32), encoding (f32s) and policy (f32s).
+pub fn write_binary<W: Write>(samples: &[Sample], shape: &[usize], out: &mut W) -> io::Result<()> {
+    let actions = samples.first().map_or(0, |sample| sample.policy.len());
+    out.write_all(MAGIC)?;
+    for n in [VERSION, shape.len() as u32].into_iter().chain(shape.iter().map(|&d| d as u32)).chain([actions as u32, samples.len() as u32]) {
+        out.write_all(&n.to_le_bytes())?;
+    }
+    for sample in samples {
+        out.write_all(&sample.key.to_le_bytes())?;
+        out.write_all(&sample.ply.to_le_bytes())?;
+        for x in [sample.outcome].iter().chain(&sample.encoding).chain(&sample.policy) {
+            out.write_all(&x.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32s<R: Read>(input: &mut R, count: usize) -> io::Result<Vec<f32>> {
+    (0..count).map(|_| read_u32(input).map(f32::from_bits)).collect()
+}
+
+// reads a file written by write_binary, giving the shape and the samples.
+pub fn read_binary<R: Read>(input: R) -> io::Result<(Vec<usize>, Vec<Sample>)> {
+    let mut input = BufReader::new(input);
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a self-play dataset"));
+    }
+    if read_u32(&mut input)? != VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown dataset version"));
+    }
+    let dims = read_u32(&mut input)?;
+    let shape = (0..dims).map(|_| read_u32(&mut input).map(|d| d as usize)).collect::<io::Result<Vec<_>>>()?;
+    let actions = read_u32(&mut input)? as usize;
+    let count = read_u32(&mut input)?;
+    let inputs = shape.iter().product();
+    let mut samples = Vec::new();
+    for _ in 0..count {
+        let mut key = [0; 8];
+        input.read_exact(&mut key)?;
+        let ply = read_u32(&mut input)?;
+        let outcome = read_f32s(&mut input, 1)?[0];
+        let encoding = read_f32s(&mut input, inputs)?;
+        let policy = read_f32s(&mut input, actions)?;
+        samples.push(Sample { encoding, policy, outcome, ply, key: u64::from_le_bytes(key) });
+    }
+    Ok((shape, samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::fs::File;
+
+    use crate::game::Player;
+    use crate::mcts::{MctsConfig, MctsPolicy};
+    use crate::rgu::Ur;
+    use crate::solver::ExpectiConfig;
+    use crate::tensor::TensorEncode;
+    use crate::tictactoe::TicTacToe;
+    use crate::winprob::{ExpectiPolicy, RandomPolicy};
+
+    use super::{generate, generate_with_chance, read_binary, write, DatasetConfig, Format};
+
+    #[test]
+    fn random_games() {
+        let config = DatasetConfig::new(30, 5);
+        let samples = generate(&TicTacToe::new(), &mut RandomPolicy, config);
+        assert_eq!(samples, generate(&TicTacToe::new(), &mut RandomPolicy, config));
+        assert_eq!(samples.iter().filter(|sample| sample.ply == 0).count(), 30);
+        for sample in &samples {
+            assert_eq!(sample.encoding.len(), 18);
+            assert_eq!(sample.policy.len(), 9);
+            assert!((sample.policy.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+            // the moves already taken have no weight.
+            let filled = sample.encoding[..9].iter().zip(&sample.encoding[9..]).map(|(a, b)| a + b);
+            assert!(filled.zip(&sample.policy).all(|(filled, &p)| filled == 0.0 || p == 0.0));
+        }
+        // the outcome flips with the side to move.
+        for pair in samples.windows(2).filter(|pair| pair[1].ply == pair[0].ply + 1) {
+            assert_eq!(pair[1].outcome, -pair[0].outcome);
+        }
+
+        let deduped = generate(&TicTacToe::new(), &mut RandomPolicy, config.with_dedup());
+        assert!(deduped.len() < samples.len());
+        assert_eq!(deduped.iter().map(|sample| sample.key).collect::<HashSet<_>>().len(), deduped.len());
+    }
+
+    #[test]
+    fn mcts_games() {
+        let config = DatasetConfig::new(4, 6).with_sample_plies(2);
+        let samples = generate(&TicTacToe::new(), &mut MctsPolicy(MctsConfig::new(200)), config);
+        assert_eq!(samples.iter().filter(|sample| sample.ply == 0).count(), 4);
+        // the policy is the share of the playouts that each move had.
+        for sample in &samples {
+            assert!((sample.policy.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+            assert!(sample.policy.iter().all(|&p| (p * 200.0 - (p * 200.0).round()).abs() < 1e-3));
+        }
+    }
+
+    #[test]
+    fn games_with_chance() {
+        let game = Ur::endgame(&[10, 4], &[9, 2], Player::X);
+        let config = DatasetConfig::new(3, 7);
+        let samples = generate_with_chance(&game, &mut ExpectiPolicy(ExpectiConfig::new(1)), config);
+        assert!(!samples.is_empty());
+        // rolls are played but aren't samples, so plies skip.
+        assert!(samples.iter().all(|sample| sample.ply % 2 == 1));
+        assert!(samples.iter().all(|sample| sample.encoding.len() == game.tensor_shape()[0]));
+    }
+
+    #[test]
+    fn write_and_read() {
+        let samples = generate(&TicTacToe::new(), &mut RandomPolicy, DatasetConfig::new(10, 8));
+        let shape = TicTacToe::new().tensor_shape();
+        let prefix = std::env::temp_dir().join(format!("selfplay-test-{}", std::process::id()));
+        let prefix = prefix.to_str().unwrap();
+
+        let config = DatasetConfig::new(10, 8).with_format(Format::Binary).with_shard_size(25);
+        let paths = write(&samples, &shape, prefix, config).unwrap();
+        assert_eq!(paths.len(), samples.len().div_ceil(25));
+        let mut read = Vec::new();
+        for path in &paths {
+            let (read_shape, shard) = read_binary(File::open(path).unwrap()).unwrap();
+            assert_eq!(read_shape, shape);
+            read.extend(shard);
+            std::fs::remove_file(path).unwrap();
+        }
+        assert_eq!(read, samples);
+
+        let paths = write(&samples, &shape, prefix, DatasetConfig::new(10, 8)).unwrap();
+        assert_eq!(paths.len(), 1);
+        let text = std::fs::read_to_string(&paths[0]).unwrap();
+        std::fs::remove_file(&paths[0]).unwrap();
+        assert_eq!(text.lines().count(), samples.len());
+        let first = text.lines().next().unwrap();
+        assert!(first.starts_with("{\"ply\":0,\"key\":\"0x"), "{}", first);
+        assert!(first.contains("\"shape\":[2,3,3],\"encoding\":[0,0,0,"), "{}", first);
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt::{self, Display, Formatter};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    game::{Game, Player, StochasticGame, ToMove},
+    record::GameRecord,
+    winprob::{Policy, RandomPolicy},
+};
+
+// plays a game of chance to the end. at chance nodes the dice are rolled by
+// sampling the game's chance model, and at decision nodes the agent for the
+// side to move picks the move: x for X and o for O. both agents are told of
+// every move played, rolls included.
+//
+// every random choice, the dice and the agents' alike, comes from one
+// generator seeded with `seed`, so the same agents with the same seed play
+// the same game again. the record holds the seed and every move.
+// a game that gets stuck, with no moves for the side to move, is stopped
+// there, and recorded without a result.
+pub fn play<G, A, B>(game: G, x: &mut A, o: &mut B, seed: u64) -> (G, GameRecord)
+where
+    G: StochasticGame,
+    A: Policy<G>,
+    B: Policy<G>,
+{
+    play_by(game, x, o, seed, |game, rng| game.sample_chance_move(rng))
+}
+
+// plays a game without chance to the end, in the same way. the seed is for
+// the agents alone.
+pub fn play_without_chance<G, A, B>(game: G, x: &mut A, o: &mut B, seed: u64) -> (G, GameRecord)
+where
+    G: Game,
+    A: Policy<G>,
+    B: Policy<G>,
+{
+    play_by(game, x, o, seed, |_, _| panic!("a chance node in a game without chance"))
+}
+
+fn play_by<G, A, B, C>(game: G, x: &mut A, o: &mut B, seed: u64, chance: C) -> (G, GameRecord)
+where
+    G: Game,
+    A: Policy<G>,
+    B: Policy<G>,
+    C: Fn(&G, &mut StdRng) -> G::Move,
+{
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut game = game;
+    let mut record = GameRecord::new(Some(seed));
+    let mut moves = Vec::with_capacity(game.action_space_size());
+    while !game.is_terminal() {
+        let m = match game.to_move() {
+            ToMove::Chance => chance(&game, &mut rng),
+            ToMove::Max | ToMove::Min => {
+                moves.clear();
+                game.generate_moves(&mut moves);
+                if moves.is_empty() {
+                    break;
+                }
+                if game.turn() == 1 {
+                    x.choose(&game, &moves, &mut rng)
+                } else {
+                    o.choose(&game, &moves, &mut rng)
+                }
+            }
+        };
+        x.observe(&game, m);
+        o.observe(&game, m);
+        game.push(m);
+        record.push(m);
+    }
+    record.result = game.outcome();
+    (game, record)
+}
+
+// plays a game with the same agent on both sides.
+pub fn self_play<G, A>(game: G, agent: &mut A, seed: u64) -> (G, GameRecord)
+where
+    G: StochasticGame,
+    A: Policy<G> + Clone,
+{
+    let mut other = agent.clone();
+    play(game, agent, &mut other, seed)
+}
+
+// the results of a match for the first agent. games that get stuck
+// without a result count as draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MatchScore {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl MatchScore {
+    pub fn games(self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    // the share of the points, counting a draw as half a win.
+    pub fn score(self) -> f64 {
+        if self.games() == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+}
+
+impl Display for MatchScore {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "+{} ={} -{} ({:.1}%)", self.wins, self.draws, self.losses, self.score() * 100.0)
+    }
+}
+
+// plays a match of games of chance between two agents, in pairs of games
+// from the starting position with the sides swapped in the second game of a
+// pair, so that neither agent gains from moving first. each game is seeded
+// from `seed`.
+pub fn play_match<G, A, B>(game: &G, first: &mut A, second: &mut B, pairs: usize, seed: u64) -> MatchScore
+where
+    G: StochasticGame,
+    A: Policy<G>,
+    B: Policy<G>,
+{
+    play_match_by(game, pairs, 0, seed, |game, first_is_x, seed| {
+        if first_is_x {
+            play(game, first, second, seed).0
+        } else {
+            play(game, second, first, seed).0
+        }
+    })
+}
+
+// the same for games without chance. each pair starts from the same few
+// random moves, so that agents that always play the same way still meet
+// in different games.
+pub fn play_match_without_chance<G, A, B>(game: &G, first: &mut A, second: &mut B, pairs: usize, opening_plies: usize, seed: u64) -> MatchScore
+where
+    G: Game,
+    A: Policy<G>,
+    B: Policy<G>,
+{
+    play_match_by(game, pairs, opening_plies, seed, |game, first_is_x, seed| {
+        if first_is_x {
+            play_without_chance(game, first, second, seed).0
+        } else {
+            play_without_chance(game, second, first, seed).0
+        }
+    })
+}
+
+fn play_match_by<G, P>(game: &G, pairs: usize, opening_plies: usize, seed: u64, mut play: P) -> MatchScore
+where
+    G: Game,
+    P: FnMut(G, bool, u64) -> G,
+{
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut score = MatchScore::default();
+    let mut moves = Vec::with_capacity(game.action_space_size());
+    for _ in 0..pairs {
+        let mut opening = game.clone();
+        for _ in 0..opening_plies {
+            moves.clear();
+            opening.generate_moves(&mut moves);
+            if opening.is_terminal() || moves.is_empty() {
+                break;
+            }
+            opening.push(RandomPolicy.choose(&opening, &moves, &mut rng));
+        }
+        for first_is_x in [true, false] {
+            let end = play(opening.clone(), first_is_x, rng.gen());
+            match end.outcome().and_then(|outcome| outcome.winner()) {
+                Some(winner) if (winner == Player::X) == first_is_x => score.wins += 1,
+                Some(_) => score.losses += 1,
+                None => score.draws += 1,
+            }
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use crate::rgu::Ur;
+    use crate::solver::ExpectiConfig;
+    use crate::winprob::{ExpectiPolicy, GreedyPolicy, RandomPolicy};
+
+    use super::{play, self_play};
+
+    #[test]
+    fn self_play_is_reproducible() {
+        for seed in 0..5 {
+            let (end, record) = self_play(Ur::new(), &mut RandomPolicy, seed);
+            assert_eq!(record.seed, Some(seed));
+            assert_eq!(end.outcome(), record.result);
+            // the record replays to the same position.
+            assert_eq!(record.replay(Ur::new()).unwrap(), end);
+            // and the seed alone plays the same game again.
+            assert_eq!(self_play(Ur::new(), &mut RandomPolicy, seed).1, record);
+        }
+        let first = self_play(Ur::new(), &mut RandomPolicy, 0).1;
+        let second = self_play(Ur::new(), &mut RandomPolicy, 1).1;
+        assert_ne!(first.moves, second.moves);
+    }
+
+    #[test]
+    fn engine_against_greedy() {
+        let mut engine = ExpectiPolicy(ExpectiConfig::new(1));
+        let (end, record) = play(Ur::endgame(&[10, 4], &[9, 2], crate::game::Player::X), &mut engine, &mut GreedyPolicy, 11);
+        assert_eq!(record.replay(Ur::endgame(&[10, 4], &[9, 2], crate::game::Player::X)).unwrap(), end);
+        assert!(!record.moves.is_empty());
+    }
+}
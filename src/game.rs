@@ -1,5 +1,10 @@
 use std::hash::Hash;
 use std::fmt::Display;
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::chance::ChanceModel;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ToMove {
@@ -8,17 +13,99 @@ pub enum ToMove {
     Chance,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Player {
+    X,
+    O,
+}
+
+impl Player {
+    // +1 for X and -1 for O, matching Game::turn().
+    pub fn sign(self) -> i8 {
+        match self {
+            Player::X => 1,
+            Player::O => -1,
+        }
+    }
+
+    pub fn opponent(self) -> Self {
+        match self {
+            Player::X => Player::O,
+            Player::O => Player::X,
+        }
+    }
+}
+
+impl Display for Player {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Player::X => write!(f, "X"),
+            Player::O => write!(f, "O"),
+        }
+    }
+}
+
+// how a finished game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Win(Player),
+    Draw,
+    // a game decided on points, such as Dots and Boxes, as the margin
+    // in X's favour. the player with more points is the winner.
+    Margin(i32),
+}
+
+impl Outcome {
+    pub fn winner(self) -> Option<Player> {
+        match self {
+            Outcome::Win(p) => Some(p),
+            Outcome::Draw => None,
+            Outcome::Margin(m) if m > 0 => Some(Player::X),
+            Outcome::Margin(m) if m < 0 => Some(Player::O),
+            Outcome::Margin(_) => None,
+        }
+    }
+
+    // +1 if X won, -1 if O won, and 0 for a draw.
+    pub fn sign(self) -> i8 {
+        self.winner().map_or(0, Player::sign)
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Outcome::Win(Player::X) => write!(f, "1-0"),
+            Outcome::Win(Player::O) => write!(f, "0-1"),
+            Outcome::Draw => write!(f, "1/2-1/2"),
+            Outcome::Margin(m) => write!(f, "X {:+}", m),
+        }
+    }
+}
+
 pub trait Game: Clone + PartialEq + Eq + Hash + Display {
     type Move: Copy + Display;
 
     fn turn(&self) -> i8;
-    fn evaluate(&self) -> i8;
-    fn is_terminal(&self) -> bool;
+    // the result of the game, or None if it isn't over yet.
+    fn outcome(&self) -> Option<Outcome>;
+
+    fn is_terminal(&self) -> bool {
+        self.outcome().is_some()
+    }
     fn generate_moves(&self, buffer: &mut Vec<Self::Move>);
     fn push(&mut self, m: Self::Move);
     fn pop(&mut self, m: Self::Move);
     fn action_space_size(&self) -> usize;
     fn to_move(&self) -> ToMove;
+
+    // a dense index for the move, in 0..action_space_size(),
+    // used to key move ordering tables.
+    fn move_index(&self, m: Self::Move) -> usize;
+
+    // reorder the moves so that the most promising come first.
+    // the default leaves them in generation order.
+    fn order_moves(&self, _buffer: &mut [Self::Move]) {}
 }
 
 pub trait Keyed: Game {
@@ -26,24 +113,55 @@ pub trait Keyed: Game {
 }
 
 pub trait StochasticGame: Game {
-    fn generate_legal_moves_with_probabilities(&self, buffer: &mut Vec<(Self::Move, f32)>);
+    type Chance: ChanceModel;
+
+    // the model that decides the outcome at chance nodes.
+    fn chance_model(&self) -> &Self::Chance;
+    // the chance move that plays the given outcome of the model.
+    fn chance_move(&self, outcome: usize) -> Self::Move;
+
+    // the outcomes of a chance node, with probabilities that sum to one.
+    fn generate_legal_moves_with_probabilities(&self, buffer: &mut Vec<(Self::Move, f64)>) {
+        assert_eq!(self.to_move(), ToMove::Chance, "generate_legal_moves_with_probabilities called on a non-chance node");
+        for (outcome, prob) in self.chance_model().outcomes() {
+            buffer.push((self.chance_move(outcome), prob));
+        }
+    }
+
+    // picks the chance move at random, with the same probabilities.
+    fn sample_chance_move<R: Rng + ?Sized>(&self, rng: &mut R) -> Self::Move {
+        assert_eq!(self.to_move(), ToMove::Chance, "sample_chance_move called on a non-chance node");
+        self.chance_move(self.chance_model().sample(rng))
+    }
 }
 
 pub trait PartiallySolvable: Game {
     fn heuristic(&self) -> i32;
 
-    fn print_outcome(&self) {
-        if !self.is_terminal() {
-            println!("nonterminal state.");
-            return;
-        }
+    // the lowest and highest values that heuristic() can return.
+    // tighter bounds let the search prune more at chance nodes.
+    fn heuristic_bounds(&self) -> (i32, i32) {
+        (-i32::MAX, i32::MAX)
+    }
 
-        let eval = self.evaluate();
-        match eval {
-            0 => println!("1/2-1/2"),
-            1 => println!("1-0"),
-            -1 => println!("0-1"),
-            _ => println!("nonstandard terminal state value: {}", eval)
+    fn print_outcome(&self) {
+        match self.outcome() {
+            Some(outcome) => println!("{}", outcome),
+            None => println!("nonterminal state."),
         }
     }
-}
\ No newline at end of file
+}
+
+// games whose heuristic is a weighted sum of features of the position,
+// so that the weights can be learned from games.
+pub trait Tunable: PartiallySolvable {
+    // the names of the features, in order.
+    fn feature_names(&self) -> &'static [&'static str];
+    // the features of this position, for X less O.
+    fn feature_vector(&self) -> Vec<f64>;
+    fn weights(&self) -> Vec<f64>;
+    // weights may be rounded to what the heuristic can use.
+    fn set_weights(&mut self, weights: &[f64]);
+    // writes the weights to a file, in the form the game reads them from.
+    fn save_weights(&self, path: &Path) -> std::io::Result<()>;
+}
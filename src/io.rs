@@ -0,0 +1,39 @@
+use std::{fmt::{self, Display, Formatter}, io, path::Path};
+
+// the errors of the text formats things are saved in, and reading them back.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+// why a file couldn't be loaded: it couldn't be read, or what it
+// holds doesn't parse.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "couldn't read the file: {}", e),
+            LoadError::Parse(e) => write!(f, "bad file, {}", e),
+        }
+    }
+}
+
+// reads a file and parses what it holds, for the things that are saved in
+// text, such as weights and networks.
+pub fn load<T>(path: impl AsRef<Path>, parse: impl FnOnce(&str) -> Result<T, ParseError>) -> Result<T, LoadError> {
+    let text = std::fs::read_to_string(path).map_err(LoadError::Io)?;
+    parse(&text).map_err(LoadError::Parse)
+}
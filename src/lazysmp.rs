@@ -0,0 +1,252 @@
+use std::sync::{Arc, atomic::{AtomicBool, AtomicU64, Ordering}};
+
+use crate::{
+    game::Keyed,
+    ordering::OrderingConfig,
+    score::Score,
+    solver::{negamax_tt, SearchContext, SearchResult, SearchStats, TTEntry, TTScore, TranspositionTable, INF},
+};
+
+// a transposition table that many threads can read and write at once
+// without locking. each slot holds a packed entry and its key xor-ed with
+// that entry, so a slot torn by two simultaneous writes fails the key check
+// on the next probe and is treated as empty, rather than returning garbage.
+pub struct SharedTT {
+    slots: Vec<Slot>,
+    shift: u32,
+}
+
+#[derive(Default)]
+struct Slot {
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+// set on every packed entry, so that an empty slot never matches a key of zero.
+const VALID: u64 = 1 << 63;
+
+impl SharedTT {
+    // the table holds the next power of two of at least `entries` slots.
+    pub fn new(entries: usize) -> Self {
+        let size = entries.next_power_of_two().max(2);
+        Self {
+            slots: (0..size).map(|_| Slot::default()).collect(),
+            shift: 64 - size.trailing_zeros(),
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        // the game keys are often raw bitboards, so mix them before indexing.
+        let index = key.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> self.shift;
+        &self.slots[index as usize]
+    }
+
+    pub fn get(&self, key: u64) -> Option<TTEntry> {
+        let slot = self.slot(key);
+        let check = slot.check.load(Ordering::Relaxed);
+        let data = slot.data.load(Ordering::Relaxed);
+        if data & VALID == 0 || check ^ data != key {
+            return None;
+        }
+        Some(unpack(data))
+    }
+
+    pub fn put(&self, key: u64, entry: TTEntry) {
+        let slot = self.slot(key);
+        let data = pack(entry);
+        slot.check.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+// layout: score in bits 0..32, depth in 32..48, bound in 48..50,
+// best move index plus one in 50..63, and the valid flag in bit 63.
+fn pack(entry: TTEntry) -> u64 {
+    let (bound, score) = match entry.value {
+        TTScore::Exact(s) => (0, s),
+        TTScore::LowerBound(s) => (1, s),
+        TTScore::UpperBound(s) => (2, s),
+    };
+    let depth = std::cmp::min(entry.depth, u16::MAX as usize) as u64;
+    let best_move = entry.best_move.map_or(0, |m| m as u64 + 1);
+    assert!(best_move < 1 << 13, "move index too large for the shared table");
+    score.raw() as u32 as u64 | depth << 32 | bound << 48 | best_move << 50 | VALID
+}
+
+fn unpack(data: u64) -> TTEntry {
+    let score = Score::from_raw(data as u32 as i32);
+    let value = match (data >> 48) & 0b11 {
+        0 => TTScore::Exact(score),
+        1 => TTScore::LowerBound(score),
+        _ => TTScore::UpperBound(score),
+    };
+    let best_move = (data >> 50) & 0x1FFF;
+    TTEntry {
+        depth: ((data >> 32) & 0xFFFF) as usize,
+        value,
+        best_move: best_move.checked_sub(1).map(|m| m as usize),
+    }
+}
+
+// each thread searches through its own shared reference.
+impl TranspositionTable for &SharedTT {
+    fn probe(&self, key: u64) -> Option<TTEntry> {
+        self.get(key)
+    }
+
+    fn store(&mut self, key: u64, entry: TTEntry) {
+        self.put(key, entry);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmpConfig {
+    pub threads: usize,
+    pub depth: usize,
+    pub tt_entries: usize,
+    pub ordering: OrderingConfig,
+}
+
+impl SmpConfig {
+    pub fn new(threads: usize, depth: usize) -> Self {
+        Self {
+            threads,
+            depth,
+            tt_entries: 1 << 20,
+            ordering: OrderingConfig::all(),
+        }
+    }
+}
+
+// lazy SMP: every thread runs its own iterative deepening search of the
+// same position, and they cooperate only through the shared transposition
+// table, where each thread finds the results of the others. helper threads
+// start at staggered depths so that they tend to work ahead of the main
+// thread rather than duplicating it. none of them search past the target
+// depth, so every table entry is one the single-threaded search could have
+// made, and the value is the same. when the main thread finishes, it stops
+// the helpers.
+pub fn lazy_smp<G>(game: G, config: SmpConfig) -> SearchResult
+where
+    G: Keyed + Send,
+{
+    let tt = SharedTT::new(config.tt_entries);
+    let stop = Arc::new(AtomicBool::new(false));
+
+    std::thread::scope(|scope| {
+        let helpers = (1..config.threads)
+            .map(|id| {
+                let (tt, stop, mut game) = (&tt, stop.clone(), game.clone());
+                scope.spawn(move || {
+                    let mut ctx = SearchContext::with_ordering(config.ordering);
+                    ctx.stop = Some(stop);
+                    let mut tt = tt;
+                    for depth in (1 + id % 3)..=config.depth {
+                        negamax_tt(&mut game, depth, -INF, INF, &mut tt, &mut ctx);
+                        if ctx.stopped() {
+                            break;
+                        }
+                    }
+                    ctx.stats
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut game = game;
+        let mut ctx = SearchContext::with_ordering(config.ordering);
+        let mut tt = &tt;
+        let mut value = Score::DRAW;
+        for depth in 1..=config.depth {
+            value = negamax_tt(&mut game, depth, -INF, INF, &mut tt, &mut ctx);
+        }
+        stop.store(true, Ordering::Relaxed);
+
+        let mut stats = ctx.stats;
+        for helper in helpers {
+            let helper_stats: SearchStats = helper.join().expect("helper thread panicked");
+            stats.nodes += helper_stats.nodes;
+        }
+
+        SearchResult {
+            value,
+            depth: config.depth,
+            stats,
+        }
+    })
+}
+
+pub fn print_smp_info(game: impl Keyed + Send, depth: usize, max_threads: usize) {
+    let mut threads = 1;
+    while threads <= max_threads {
+        let start = std::time::Instant::now();
+        let result = lazy_smp(game.clone(), SmpConfig::new(threads, depth));
+        let time = start.elapsed().as_secs_f32();
+        println!(
+            "{:>2} threads: value {:>9}, {:>10} nodes, done in {:.3}s, at {:.2} Mnps",
+            threads,
+            result.value.to_string(),
+            result.stats.nodes,
+            time,
+            result.stats.nodes as f32 / time / 1_000_000.0);
+        threads *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::coverttt::{CoverTTT, CoverTTTMove, Size};
+    use crate::game::Game;
+    use crate::score::Score;
+    use crate::solver::{negamax_tt, solve, SearchContext, TTEntry, TTScore, INF};
+    use crate::tictactoe::TicTacToe;
+
+    use super::{lazy_smp, pack, unpack, SharedTT, SmpConfig};
+
+    #[test]
+    fn packing_roundtrip() {
+        let entries = [
+            TTEntry { depth: 7, value: TTScore::Exact(Score::win_in(3)), best_move: Some(26) },
+            TTEntry { depth: 0, value: TTScore::LowerBound(Score::loss_in(12)), best_move: None },
+            TTEntry { depth: 1000, value: TTScore::UpperBound(Score::heuristic(-345)), best_move: Some(0) },
+        ];
+        for entry in entries {
+            assert_eq!(unpack(pack(entry)), entry);
+        }
+    }
+
+    #[test]
+    fn shared_table() {
+        let tt = SharedTT::new(16);
+        let entry = TTEntry { depth: 3, value: TTScore::Exact(Score::DRAW), best_move: Some(4) };
+        // a zero key must not match an empty slot.
+        assert_eq!(tt.get(0), None);
+        tt.put(0, entry);
+        assert_eq!(tt.get(0), Some(entry));
+        assert_eq!(tt.get(12345), None);
+    }
+
+    #[test]
+    fn ttt_matches_single_threaded() {
+        for threads in [1, 2, 4] {
+            for depth in [4, 9] {
+                let single = negamax_tt(&mut TicTacToe::new(), depth, -INF, INF, &mut HashMap::new(), &mut SearchContext::new());
+                let parallel = lazy_smp(TicTacToe::new(), SmpConfig::new(threads, depth));
+                assert_eq!(parallel.value, single);
+            }
+        }
+    }
+
+    #[test]
+    fn cttt_matches_solve() {
+        let mut root = CoverTTT::new();
+        root.push(CoverTTTMove::new(4, Size::Big));
+        root.push(CoverTTTMove::new(1, Size::Small));
+        let parallel = lazy_smp(root, SmpConfig::new(4, 8));
+        // X is to move and wins in five, as in the single-threaded solve.
+        assert_eq!(solve(root), 5);
+        assert_eq!(parallel.value, Score::win_in(5));
+        assert!(parallel.stats.nodes > 0);
+    }
+}
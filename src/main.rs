@@ -1,23 +1,65 @@
 #![allow(dead_code)]
 
 mod iter_bits;
+mod chance;
 mod game;
 mod tictactoe;
 mod coverttt;
-// mod connect4;
+mod connect4;
 mod perft;
 mod solver;
 mod rgu;
 mod adversarialknight;
+mod certificate;
+mod ordering;
+mod score;
+mod lazysmp;
+mod winprob;
+mod record;
+mod io;
+mod driver;
+mod ureval;
+mod tuner;
+mod urnet;
+mod tensor;
+mod mcts;
+mod dataset;
+mod alphazero;
+#[cfg(test)]
+mod testing;
 
-use game::PartiallySolvable;
-use solver::expectiminimax;
+use std::{fmt::Display, io::IsTerminal};
 
-use crate::{rgu::Ur, game::Game, solver::expecti_best_move, tictactoe::TicTacToe, coverttt::CoverTTT};
+use game::{PartiallySolvable, StochasticGame, Tunable};
+use solver::{expectiminimax_ab, ChancePruning, ExpectiConfig, SearchStats};
+use winprob::{ExpectiPolicy, GreedyPolicy, Policy};
 
-fn play_human<G: Game + PartiallySolvable>(mut game: G) {
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{rgu::Ur, game::Game, score::ExpectedValue, tictactoe::TicTacToe, ureval::UrWeights};
+
+// asks at the terminal for one of the moves, until a legal one is typed in.
+fn read_move<M: Copy + Display>(moves: &[M]) -> M {
     use std::io::{stdin,stdout,Write};
     let mut userinput = String::new();
+    loop {
+        println!("legal moves:");
+        for &m in moves {
+            print!("{}, ", m);
+        }
+        println!();
+        let _ = stdout().flush();
+        userinput.clear();
+        stdin().read_line(&mut userinput).expect("Did not enter a correct string.");
+        let str_move = userinput.trim();
+        match moves.iter().find(|m| format!("{}", m) == str_move) {
+            Some(&m) => return m,
+            None => println!("Move not found."),
+        }
+    }
+}
+
+fn play_human<G: Game + PartiallySolvable>(mut game: G) {
     while !game.is_terminal() {
         println!("{}", game);
         if game.turn() == -1 {
@@ -28,35 +70,251 @@ fn play_human<G: Game + PartiallySolvable>(mut game: G) {
         }
         let mut lmoves = Vec::new();
         game.generate_moves(&mut lmoves);
-        println!("legal moves:");
-        for &m in &lmoves {
-            print!("{}, ", m);
-        }
-        println!();
-        let _ = stdout().flush();
-        stdin().read_line(&mut userinput).expect("Did not enter a correct string.");
-        let str_move = userinput.trim();
-        let user_move = *lmoves.iter().find(|m| {
-            format!("{}", m) == str_move
-        }).expect("Move not found.");
-        game.push(user_move);
-        userinput.clear();
+        game.push(read_move(&lmoves));
+    }
+    println!("{}", game);
+    game.print_outcome();
+}
+
+// a player at the terminal, for games played by driver::play.
+// every move is printed as it's played, so the player sees the dice
+// and the computer's moves as well as their own.
+struct Human;
+
+impl<G: Game> Policy<G> for Human {
+    fn choose<R: Rng>(&mut self, game: &G, moves: &[G::Move], _rng: &mut R) -> G::Move {
+        println!("{}", game);
+        read_move(moves)
+    }
+
+    fn observe(&mut self, game: &G, m: G::Move) {
+        println!("{} {}", if game.turn() == 1 { "X" } else { "O" }, m);
     }
+}
+
+// the human plays X against an expectimax search, with the dice rolled
+// from the given seed.
+fn play_human_with_dice<G: StochasticGame + PartiallySolvable>(game: G, seed: u64) {
+    let mut engine = ExpectiPolicy(ExpectiConfig::new(3));
+    let (game, record) = driver::play(game, &mut Human, &mut engine, seed);
     println!("{}", game);
     game.print_outcome();
+    println!();
+    print!("{}", record);
+}
+
+// the value given after a command line flag, as in `--seed 42`.
+fn arg_value(flag: &str) -> Option<String> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let i = args.iter().position(|arg| arg == flag)?;
+    Some(args.get(i + 1).unwrap_or_else(|| panic!("{} takes a value", flag)).clone())
+}
+
+// whether a flag without a value, like `--dedup`, was given.
+fn has_flag(flag: &str) -> bool {
+    std::env::args().any(|arg| arg == flag)
+}
+
+// the seed for every random choice in the run, given with `--seed <n>`.
+// without one, a seed is picked at random, and printed so that the run
+// can be repeated.
+fn seed_from_args() -> u64 {
+    match arg_value("--seed") {
+        Some(seed) => seed.parse().expect("--seed takes a number"),
+        None => rand::random(),
+    }
+}
+
+// the weights of the Ur heuristic, read from the file given with
+// `--weights <path>`, or the defaults.
+fn weights_from_args() -> UrWeights {
+    match arg_value("--weights") {
+        Some(path) => UrWeights::load(&path).unwrap_or_else(|e| panic!("{}: {}", path, e)),
+        None => UrWeights::default(),
+    }
+}
+
+// the dataset config from `--games <n>`, `--format jsonl|bin`, `--shard <n>`
+// and `--dedup`.
+fn dataset_config_from_args(seed: u64) -> dataset::DatasetConfig {
+    let games = arg_value("--games").map_or(100, |games| games.parse().expect("--games takes a number"));
+    let mut config = dataset::DatasetConfig::new(games, seed);
+    match arg_value("--format").as_deref() {
+        None | Some("jsonl") => {},
+        Some("bin") => config = config.with_format(dataset::Format::Binary),
+        Some(format) => panic!("unknown format {}, expected jsonl or bin", format),
+    }
+    if let Some(size) = arg_value("--shard") {
+        match size.parse() {
+            Ok(0) => panic!("--shard takes a number of samples above zero"),
+            Ok(size) => config = config.with_shard_size(size),
+            Err(_) => panic!("--shard takes a number"),
+        }
+    }
+    if has_flag("--dedup") {
+        config = config.with_dedup();
+    }
+    config
+}
+
+fn write_dataset<G: tensor::TensorEncode>(game: &G, samples: &[dataset::Sample], prefix: &str, config: dataset::DatasetConfig) {
+    let paths = dataset::write(samples, &game.tensor_shape(), prefix, config).expect("couldn't write the dataset");
+    println!("{} samples from {} games", samples.len(), config.games);
+    for path in paths {
+        println!("wrote {}", path.display());
+    }
+}
+
+// plays self-play games of a game without chance with `--agent random|mcts`,
+// and writes them out as training data.
+fn selfplay<G: tensor::TensorEncode + game::Keyed>(game: G, prefix: &str, config: dataset::DatasetConfig) {
+    let samples = match arg_value("--agent").as_deref() {
+        None | Some("mcts") => {
+            let playouts = arg_value("--playouts").map_or(800, |n| n.parse().expect("--playouts takes a number"));
+            dataset::generate(&game, &mut mcts::MctsPolicy(mcts::MctsConfig::new(playouts)), config)
+        },
+        Some("random") => dataset::generate(&game, &mut winprob::RandomPolicy, config),
+        Some(agent) => panic!("unknown agent {} for this game, expected mcts or random", agent),
+    };
+    write_dataset(&game, &samples, prefix, config);
+}
+
+// trains a policy-value network from self-play, after `--alphazero <dir>`,
+// for `--iterations <n>`, starting from the network in `--from <path>` if
+// there is one. `--games <n>` and `--playouts <n>` set the self-play games
+// per iteration and the search for each move.
+fn alphazero<G: tensor::TensorEncode + game::Keyed>(game: G, dir: &str, config: alphazero::TrainConfig, rng: &mut StdRng) {
+    let mut config = config;
+    if let Some(iterations) = arg_value("--iterations") {
+        config.iterations = iterations.parse().expect("--iterations takes a number");
+    }
+    if let Some(games) = arg_value("--games") {
+        config = config.with_games(games.parse().expect("--games takes a number"));
+    }
+    if let Some(playouts) = arg_value("--playouts") {
+        config = config.with_playouts(playouts.parse().expect("--playouts takes a number"));
+    }
+    let net = match arg_value("--from") {
+        Some(path) => alphazero::PolicyValueNet::load(&path).unwrap_or_else(|e| panic!("{}: {}", path, e)),
+        None => alphazero::PolicyValueNet::for_game(&game, config.hidden, rng),
+    };
+    println!("{}", alphazero::IterationReport::HEADER);
+    let dir = std::path::Path::new(dir);
+    alphazero::train(&game, net, config, Some(dir), |report| println!("{}", report)).expect("couldn't write the checkpoints");
+    println!("networks and the learning curve are in {}", dir.display());
 }
 
 fn main() {
+    let seed = seed_from_args();
+    println!("seed {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let weights = weights_from_args();
+    println!();
+
+    // `--play ur` plays a game of Ur against the engine at the terminal.
+    if let Some(game) = arg_value("--play") {
+        match game.as_str() {
+            "ur" => play_human_with_dice(Ur::new().with_weights(weights), seed),
+            game => panic!("unknown game {}, expected ur", game),
+        }
+        return;
+    }
+
+    // `--tune <path>` learns the Ur weights from greedy self-play
+    // instead, and writes them out.
+    if let Some(path) = arg_value("--tune") {
+        let config = tuner::TunerConfig::new(2000, tuner::Method::Texel { iterations: 20 }, seed);
+        let game = Ur::new().with_weights(weights);
+        let report = tuner::tune(&game, &GreedyPolicy, config);
+        println!("tuned on {} positions", report.positions);
+        println!("train loss {:.4} -> {:.4}", report.initial_train_loss, report.train_loss);
+        println!("validation loss {:.4} -> {:.4}", report.initial_validation_loss, report.validation_loss);
+        let mut tuned = game.clone();
+        tuned.set_weights(&report.weights);
+        tuned.save_weights(std::path::Path::new(&path)).expect("couldn't write the weights");
+        print!("{}", std::fs::read_to_string(&path).unwrap());
+        return;
+    }
+
+    // `--alphazero <dir>` trains a network for the game given with
+    // `--game ttt|cttt|c4`. Connect 4 is too big to solve, so its
+    // evaluation matches are against a six ply search instead.
+    if let Some(dir) = arg_value("--alphazero") {
+        let config = alphazero::TrainConfig::new(10, seed);
+        match arg_value("--game").as_deref() {
+            None | Some("ttt") => alphazero(TicTacToe::new(), &dir, config, &mut rng),
+            Some("cttt") => alphazero(coverttt::CoverTTT::new(), &dir, config, &mut rng),
+            Some("c4") => alphazero(connect4::Connect4::new(), &dir, config.with_hidden(128).with_solver_depth(6), &mut rng),
+            Some(game) => panic!("unknown game {}, expected ttt, cttt or c4", game),
+        }
+        return;
+    }
+
+    // `--selfplay <prefix>` writes training data from self-play games of the
+    // game given with `--game ttt|cttt|c4|ur`.
+    if let Some(prefix) = arg_value("--selfplay") {
+        let config = dataset_config_from_args(seed);
+        match arg_value("--game").as_deref() {
+            None | Some("ttt") => selfplay(TicTacToe::new(), &prefix, config),
+            Some("cttt") => selfplay(coverttt::CoverTTT::new(), &prefix, config),
+            Some("c4") => selfplay(connect4::Connect4::new(), &prefix, config),
+            // Ur has dice, so it's played by expectimax or at random.
+            Some("ur") => {
+                let game = Ur::new().with_weights(weights);
+                let samples = match arg_value("--agent").as_deref() {
+                    None | Some("expecti") => dataset::generate_with_chance(&game, &mut ExpectiPolicy(ExpectiConfig::new(2)), config),
+                    Some("random") => dataset::generate_with_chance(&game, &mut winprob::RandomPolicy, config),
+                    Some(agent) => panic!("unknown agent {} for Ur, expected expecti or random", agent),
+                };
+                write_dataset(&game, &samples, &prefix, config);
+            },
+            Some(game) => panic!("unknown game {}, expected ttt, cttt, c4 or ur", game),
+        }
+        return;
+    }
+
+    // `--train-net <path>` trains a value network for Ur by TD(λ) and writes
+    // it out, and `--net <path>` reads one. either way, the network is then
+    // matched against the handcrafted heuristic, both searched by expectimax.
+    let net = match (arg_value("--train-net"), arg_value("--net")) {
+        (Some(path), _) => {
+            let mut net = urnet::new_ur_net(40, &mut rng);
+            let config = urnet::TdConfig { games: 20_000, lambda: 0.7, alpha: 0.1, seed };
+            let start = std::time::Instant::now();
+            urnet::train(&mut net, &Ur::new(), config);
+            println!("trained on {} games in {:.1}s", config.games, start.elapsed().as_secs_f32());
+            net.save(&path).expect("couldn't write the network");
+            Some(net)
+        },
+        (None, Some(path)) => Some(urnet::Mlp::load(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))),
+        (None, None) => None,
+    };
+    if let Some(net) = net {
+        let search = ExpectiConfig::new(2);
+        let mut learned = urnet::UrEngine { config: search, net: Some(std::sync::Arc::new(net)), weights };
+        let mut handcrafted = urnet::UrEngine { config: search, net: None, weights };
+        let result = driver::play_match(&Ur::new(), &mut learned, &mut handcrafted, 100, seed);
+        println!("network against the heuristic at depth {}: {}", search.depth, result);
+        return;
+    }
+
     // println!("Solving Noughts and Crosses");
     // solver::print_solve_info(TicTacToe::new());
     // println!();
     // println!("Solving Cover Tic-Tac-Toe");
     // solver::print_solve_info(CoverTTT::new());
     // println!();
+    // println!("Solving Cover Tic-Tac-Toe in parallel");
+    // lazysmp::print_smp_info(CoverTTT::new(), 18, 8);
+    // println!();
     // println!("Solving the Adversarial Knight");
     // solver::print_solve_info(adversarialknight::AdversarialKnight::new());
     // println!();
 
+    println!("Comparing search algorithms on Noughts and Crosses");
+    solver::print_search_comparison(TicTacToe::new(), 9);
+    println!();
+
     println!("Perft testing Noughts and Crosses");
     perft::perft_test(TicTacToe::new());
     println!();
@@ -67,28 +325,32 @@ fn main() {
     perft::perft_test(Ur::new());
     println!();
 
-    let mut rgu = Ur::new();
+    println!("Win probabilities after rolling a 2 in Ur, with greedy play");
+    // the full game has far too many positions to solve, so it goes
+    // straight to rollouts rather than exploring first.
+    let mut analyser = winprob::Analyser::new(winprob::GreedyPolicy, &mut rng).with_max_states(0).with_rollouts(200);
+    for analysis in analyser.analyse(&Ur::new().with_weights(weights), rgu::UrMove::Roll(2)) {
+        println!("{}: {}", analysis.m, analysis.value);
+    }
+    println!();
+
+    println!("Ur self-play, expectimax against greedy play");
+    let mut engine = ExpectiPolicy(ExpectiConfig::new(2));
+    let (end, record) = driver::play(Ur::new().with_weights(weights), &mut engine, &mut GreedyPolicy, seed);
+    println!("{}", end.rendering().with_colour(std::io::stdout().is_terminal()));
+    println!("result {} after {} moves", end.outcome().map_or("*".to_string(), |o| o.to_string()), record.moves.len());
+    println!();
+
+    let mut rgu = Ur::new().with_weights(weights);
     for depth in 1..20 {
         let before = rgu.clone();
         let start = std::time::Instant::now();
-        let eval = expectiminimax(&mut rgu, depth);
+        let mut stats = SearchStats::default();
+        let eval = expectiminimax_ab(&mut rgu, depth, -ExpectedValue::INFINITY, ExpectedValue::INFINITY, ChancePruning::Star2, &mut stats);
         let time = start.elapsed().as_secs_f32();
         assert!(rgu == before);
-        println!("eval {} at depth {}, done in {:.1}s", eval, depth, time);
-    }
-
-    // let mut rgu = Ur::new();
-    // while !rgu.is_terminal() {
-    //     let m = expecti_best_move(rgu.clone());
-    //     println!("{}", rgu);
-    //     let before = rgu.clone();
-    //     println!("eval: {}", expectiminimax(&mut rgu, 5));
-    //     println!("best move: {:?}", m);
-    //     assert_eq!(rgu, before);
-    //     rgu.push(m);
-    // }
-    // println!("{}", rgu);
-
+        println!("eval {} at depth {}, {} nodes, done in {:.1}s", eval, depth, stats.nodes, time);
+    }
 
     // println!("CTTT opening move evaluations (sorted worst-to-best):");
     // let mut root = CoverTTT::new();
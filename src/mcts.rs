@@ -0,0 +1,209 @@
+use rand::Rng;
+
+use crate::{
+    game::{Game, ToMove},
+    winprob::{favourite_move, result_for_x, Policy},
+};
+
+// Monte Carlo tree search with UCT, for games without chance. the tree grows
+// by one node each playout: the search walks down from the root, picking the
+// child with the best upper confidence bound, adds one untried move at the
+// bottom, and plays the rest of the game out at random. the result is then
+// counted at every node on the way back up.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MctsConfig {
+    pub playouts: usize,
+    // the weight of the exploration term in the UCT formula. larger values
+    // spread the playouts more evenly over the moves.
+    pub exploration: f64,
+}
+
+impl MctsConfig {
+    pub fn new(playouts: usize) -> Self {
+        Self {
+            playouts,
+            exploration: std::f64::consts::SQRT_2,
+        }
+    }
+
+    pub fn with_exploration(self, exploration: f64) -> Self {
+        Self {
+            exploration,
+            ..self
+        }
+    }
+}
+
+struct Node<M> {
+    // the move that leads here from the parent, None at the root.
+    m: Option<M>,
+    // +1 if X made that move, and -1 if O did.
+    mover: i8,
+    children: Vec<usize>,
+    untried: Vec<M>,
+    visits: u32,
+    // the results of the playouts through this node for the mover:
+    // one for a win and a half for a draw.
+    score: f64,
+}
+
+impl<M: Copy> Node<M> {
+    fn new<G: Game<Move = M>>(game: &G, m: Option<M>, mover: i8) -> Self {
+        let mut untried = Vec::new();
+        if !game.is_terminal() {
+            assert_ne!(game.to_move(), ToMove::Chance, "MCTS searches games without chance");
+            game.generate_moves(&mut untried);
+        }
+        Self { m, mover, children: Vec::new(), untried, visits: 0, score: 0.0 }
+    }
+
+    fn upper_bound(&self, log_parent_visits: f64, exploration: f64) -> f64 {
+        let visits = self.visits as f64;
+        self.score / visits + exploration * (log_parent_visits / visits).sqrt()
+    }
+}
+
+// the number of playouts that went through each of the moves at the root,
+// in the order that the game generates them.
+pub fn mcts<G: Game, R: Rng>(game: &G, config: MctsConfig, rng: &mut R) -> Vec<(G::Move, u32)> {
+    let mut nodes = vec![Node::new(game, None, -game.turn())];
+    let mut path = Vec::new();
+    let mut moves = Vec::with_capacity(game.action_space_size());
+    for _ in 0..config.playouts {
+        let mut game = game.clone();
+        path.clear();
+        path.push(0);
+        let mut current = 0;
+        while nodes[current].untried.is_empty() && !nodes[current].children.is_empty() {
+            let log_visits = (nodes[current].visits as f64).ln();
+            current = *nodes[current]
+                .children
+                .iter()
+                .max_by(|&&a, &&b| {
+                    let a = nodes[a].upper_bound(log_visits, config.exploration);
+                    let b = nodes[b].upper_bound(log_visits, config.exploration);
+                    a.total_cmp(&b)
+                })
+                .unwrap();
+            game.push(nodes[current].m.unwrap());
+            path.push(current);
+        }
+        if !nodes[current].untried.is_empty() {
+            let untried = &mut nodes[current].untried;
+            let m = untried.swap_remove(rng.gen_range(0..untried.len()));
+            let mover = game.turn();
+            game.push(m);
+            nodes.push(Node::new(&game, Some(m), mover));
+            let child = nodes.len() - 1;
+            nodes[current].children.push(child);
+            path.push(child);
+        }
+        // a position with no moves that isn't over counts as a draw.
+        let x_score = loop {
+            if let Some(result) = result_for_x(&game) {
+                break result;
+            }
+            moves.clear();
+            game.generate_moves(&mut moves);
+            if moves.is_empty() {
+                break 0.5;
+            }
+            game.push(moves[rng.gen_range(0..moves.len())]);
+        };
+        for &i in &path {
+            let node = &mut nodes[i];
+            node.visits += 1;
+            node.score += if node.mover == 1 { x_score } else { 1.0 - x_score };
+        }
+    }
+    moves.clear();
+    game.generate_moves(&mut moves);
+    moves
+        .into_iter()
+        .map(|m| {
+            let index = game.move_index(m);
+            let visits = nodes[0]
+                .children
+                .iter()
+                .map(|&i| &nodes[i])
+                .find(|child| game.move_index(child.m.unwrap()) == index)
+                .map_or(0, |child| child.visits);
+            (m, visits)
+        })
+        .collect()
+}
+
+// plays the move that MCTS visits most, breaking ties at random.
+#[derive(Debug, Clone, Copy)]
+pub struct MctsPolicy(pub MctsConfig);
+
+impl<G: Game> Policy<G> for MctsPolicy {
+    fn choose<R: Rng>(&mut self, game: &G, moves: &[G::Move], rng: &mut R) -> G::Move {
+        let distribution = self.distribution(game, moves, rng);
+        favourite_move(moves, &distribution, rng)
+    }
+
+    // the share of the playouts that went through each move.
+    fn distribution<R: Rng>(&mut self, game: &G, moves: &[G::Move], rng: &mut R) -> Vec<f64> {
+        let visits = mcts(game, self.0, rng);
+        let total = visits.iter().map(|&(_, n)| n as f64).sum::<f64>().max(1.0);
+        moves
+            .iter()
+            .map(|&m| {
+                let index = game.move_index(m);
+                visits.iter().find(|&&(v, _)| game.move_index(v) == index).map_or(0.0, |&(_, n)| n as f64 / total)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::connect4::Connect4;
+    use crate::game::Game;
+    use crate::tictactoe::{TicTacToe, TicTacToeMove};
+    use crate::winprob::Policy;
+
+    use super::{mcts, MctsConfig, MctsPolicy};
+
+    #[test]
+    fn visits_add_up() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let visits = mcts(&TicTacToe::new(), MctsConfig::new(500), &mut rng);
+        assert_eq!(visits.len(), 9);
+        assert_eq!(visits.iter().map(|&(_, n)| n).sum::<u32>(), 500);
+
+        // X, with two in the top row, spends most playouts on finishing it.
+        let mut game = TicTacToe::new();
+        for m in [0, 3, 1, 4] {
+            game.push(TicTacToeMove::new(m));
+        }
+        let visits = mcts(&game, MctsConfig::new(500), &mut rng);
+        let win = visits.iter().find(|&&(m, _)| m == TicTacToeMove::new(2)).unwrap().1;
+        assert!(visits.iter().all(|&(_, n)| n <= win), "{:?}", visits);
+    }
+
+    #[test]
+    fn finds_wins_and_blocks() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut policy = MctsPolicy(MctsConfig::new(1000));
+        // X has three along the bottom, and takes the fourth.
+        let mut game = Connect4::new();
+        for m in [0, 0, 1, 1, 2, 2] {
+            game.push(m);
+        }
+        let mut moves = Vec::new();
+        game.generate_moves(&mut moves);
+        assert_eq!(policy.choose(&game, &moves, &mut rng), 3);
+        // if X plays elsewhere, O has to block it.
+        game.push(6);
+        moves.clear();
+        game.generate_moves(&mut moves);
+        assert_eq!(policy.choose(&game, &moves, &mut rng), 3);
+        let distribution = policy.distribution(&game, &moves, &mut rng);
+        assert!((distribution.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+}
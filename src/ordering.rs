@@ -0,0 +1,155 @@
+use std::cmp::Reverse;
+
+use crate::game::Game;
+
+// which sources of move ordering information the search should use.
+// with everything switched off, moves are searched in the order that
+// generate_moves emits them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OrderingConfig {
+    // let the game reorder its own moves with Game::order_moves.
+    pub game_order: bool,
+    // search the best move stored in the transposition table first.
+    pub tt_move: bool,
+    // search the two most recent moves that caused a cutoff at this ply next.
+    pub killers: bool,
+    // order the remaining moves by how often they have caused cutoffs.
+    pub history: bool,
+}
+
+impl OrderingConfig {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn all() -> Self {
+        Self {
+            game_order: true,
+            tt_move: true,
+            killers: true,
+            history: true,
+        }
+    }
+}
+
+// the tables behind the ordering heuristics. moves are stored by their
+// Game::move_index, so a single table serves every game.
+#[derive(Debug, Clone, Default)]
+pub struct MoveOrdering {
+    pub config: OrderingConfig,
+    killers: Vec<[Option<usize>; 2]>,
+    history: Vec<u64>,
+}
+
+impl MoveOrdering {
+    pub fn new(config: OrderingConfig) -> Self {
+        Self {
+            config,
+            killers: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.killers.clear();
+        self.history.clear();
+    }
+
+    pub fn order<G: Game>(&self, game: &G, buffer: &mut [G::Move], ply: usize, tt_move: Option<usize>) {
+        let config = self.config;
+        if config.game_order {
+            game.order_moves(buffer);
+        }
+        if !(config.tt_move || config.killers || config.history) {
+            return;
+        }
+        // the sort is stable, so moves that score the same
+        // keep the order given by the game.
+        buffer.sort_by_key(|&m| Reverse(self.score(game.move_index(m), ply, tt_move)));
+    }
+
+    fn score(&self, idx: usize, ply: usize, tt_move: Option<usize>) -> u64 {
+        if self.config.tt_move && tt_move == Some(idx) {
+            return u64::MAX;
+        }
+        if self.config.killers {
+            if let Some(killers) = self.killers.get(ply) {
+                if killers[0] == Some(idx) {
+                    return u64::MAX - 1;
+                }
+                if killers[1] == Some(idx) {
+                    return u64::MAX - 2;
+                }
+            }
+        }
+        if self.config.history {
+            return self.history.get(idx).copied().unwrap_or(0);
+        }
+        0
+    }
+
+    // called when a move causes a beta cutoff, so that it is tried
+    // earlier in sibling positions and elsewhere in the tree.
+    pub fn record_cutoff<G: Game>(&mut self, game: &G, m: G::Move, ply: usize, depth: usize) {
+        let idx = game.move_index(m);
+        if self.config.killers {
+            if self.killers.len() <= ply {
+                self.killers.resize(ply + 1, [None; 2]);
+            }
+            let killers = &mut self.killers[ply];
+            if killers[0] != Some(idx) {
+                killers[1] = killers[0];
+                killers[0] = Some(idx);
+            }
+        }
+        if self.config.history {
+            if self.history.len() <= idx {
+                self.history.resize(game.action_space_size().max(idx + 1), 0);
+            }
+            // deeper cutoffs save more work, so they count for more.
+            self.history[idx] += (depth * depth) as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use crate::tictactoe::{TicTacToe, TicTacToeMove};
+
+    use super::{MoveOrdering, OrderingConfig};
+
+    fn root_moves() -> (TicTacToe, Vec<TicTacToeMove>) {
+        let game = TicTacToe::new();
+        let mut moves = Vec::new();
+        game.generate_moves(&mut moves);
+        (game, moves)
+    }
+
+    #[test]
+    fn no_ordering_keeps_generation_order() {
+        let (game, mut moves) = root_moves();
+        let before = moves.clone();
+        MoveOrdering::new(OrderingConfig::none()).order(&game, &mut moves, 0, Some(5));
+        assert_eq!(moves, before);
+    }
+
+    #[test]
+    fn tt_move_then_killers_then_history() {
+        let (game, mut moves) = root_moves();
+        let mut ordering = MoveOrdering::new(OrderingConfig { game_order: false, ..OrderingConfig::all() });
+        ordering.record_cutoff(&game, TicTacToeMove::new(8), 3, 4);
+        ordering.record_cutoff(&game, TicTacToeMove::new(2), 0, 1);
+        ordering.record_cutoff(&game, TicTacToeMove::new(6), 0, 1);
+        ordering.order(&game, &mut moves, 0, Some(4));
+        assert_eq!(&moves[..4], &[4, 6, 2, 8].map(TicTacToeMove::new));
+    }
+
+    #[test]
+    fn game_order() {
+        let (game, mut moves) = root_moves();
+        MoveOrdering::new(OrderingConfig { game_order: true, ..OrderingConfig::none() }).order(&game, &mut moves, 0, None);
+        // the centre is the strongest opening square.
+        assert_eq!(moves[0], TicTacToeMove::new(4));
+    }
+}
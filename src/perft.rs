@@ -1,6 +1,6 @@
 use std::{collections::HashMap, fmt::Display};
 
-use crate::game::Game;
+use crate::game::{Game, Player};
 
 pub fn perft<G: Game + Display>(board: &mut G, depth: u8) -> u64 {
     eprintln!("depth {}, board \n{}", depth, board);
@@ -25,6 +25,45 @@ pub fn perft<G: Game + Display>(board: &mut G, depth: u8) -> u64 {
     nodes
 }
 
+// tallies of how the games counted by perft ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutcomeCounts {
+    pub x_wins: u64,
+    pub o_wins: u64,
+    pub draws: u64,
+    // lines that were still going when the depth ran out.
+    pub unfinished: u64,
+}
+
+pub fn perft_outcomes<G: Game>(board: &mut G, depth: u8) -> OutcomeCounts {
+    let mut counts = OutcomeCounts::default();
+    perft_outcomes_internal(board, depth, &mut counts);
+    counts
+}
+
+fn perft_outcomes_internal<G: Game>(board: &mut G, depth: u8, counts: &mut OutcomeCounts) {
+    if let Some(outcome) = board.outcome() {
+        match outcome.winner() {
+            Some(Player::X) => counts.x_wins += 1,
+            Some(Player::O) => counts.o_wins += 1,
+            None => counts.draws += 1,
+        }
+        return;
+    }
+    if depth == 0 {
+        counts.unfinished += 1;
+        return;
+    }
+
+    let mut moves = Vec::with_capacity(27);
+    board.generate_moves(&mut moves);
+    for m in moves {
+        board.push(m);
+        perft_outcomes_internal(board, depth - 1, counts);
+        board.pop(m);
+    }
+}
+
 fn perft_cached_internal<G: Game>(board: &mut G, depth: u8, seen: &mut HashMap<G, u64>) -> u64 {
     if board.is_terminal() {
         return 1;
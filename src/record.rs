@@ -0,0 +1,170 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{game::{Game, Outcome, Player}, io::ParseError};
+
+// a saved game: its moves in move notation and how it ended. games with
+// chance moves or random agents also record the seed of the random number
+// generator that made those choices, so that the game can be played again
+// exactly as it went.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GameRecord {
+    pub seed: Option<u64>,
+    pub moves: Vec<String>,
+    // None if the game was stopped before it ended.
+    pub result: Option<Outcome>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    // the move at this index is not among the legal moves.
+    IllegalMove { index: usize, notation: String },
+    // replaying the moves ended differently from the recorded result.
+    WrongResult { recorded: Option<Outcome>, actual: Option<Outcome> },
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ReplayError::IllegalMove { index, notation } => write!(f, "move {} ({}) is illegal", index + 1, notation),
+            ReplayError::WrongResult { recorded, actual } => write!(f, "recorded result {}, but the moves give {}", show(recorded), show(actual)),
+        }
+    }
+}
+
+fn show(result: &Option<Outcome>) -> String {
+    result.map_or("*".to_string(), |outcome| outcome.to_string())
+}
+
+impl GameRecord {
+    pub fn new(seed: Option<u64>) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+
+    pub fn push<M: Display>(&mut self, m: M) {
+        self.moves.push(m.to_string());
+    }
+
+    // plays the recorded moves from the given starting position,
+    // checking each against the legal moves, and returns the final position.
+    pub fn replay<G: Game>(&self, game: G) -> Result<G, ReplayError> {
+        let mut game = game;
+        let mut buffer = Vec::with_capacity(game.action_space_size());
+        for (index, notation) in self.moves.iter().enumerate() {
+            buffer.clear();
+            game.generate_moves(&mut buffer);
+            let m = buffer
+                .iter()
+                .find(|m| m.to_string() == *notation)
+                .ok_or_else(|| ReplayError::IllegalMove { index, notation: notation.clone() })?;
+            game.push(*m);
+        }
+        if game.outcome() != self.result {
+            return Err(ReplayError::WrongResult { recorded: self.result, actual: game.outcome() });
+        }
+        Ok(game)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let err = |line, message: &str| ParseError { line, message: message.to_string() };
+        let mut record = Self::default();
+        for (line, text) in text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+            if text.is_empty() {
+                continue;
+            }
+            if let Some(seed) = text.strip_prefix("seed ") {
+                record.seed = Some(seed.parse().map_err(|_| err(line, "invalid seed"))?);
+            } else if let Some(result) = text.strip_prefix("result ") {
+                record.result = parse_result(result).ok_or_else(|| err(line, "invalid result"))?;
+            } else if let Some(m) = text.strip_prefix("move ") {
+                record.moves.push(m.to_string());
+            } else {
+                return Err(err(line, "expected \"seed\", \"result\" or \"move\""));
+            }
+        }
+        Ok(record)
+    }
+}
+
+// the inverse of Outcome's Display, with "*" for a game that isn't over.
+fn parse_result(text: &str) -> Option<Option<Outcome>> {
+    match text {
+        "*" => Some(None),
+        "1-0" => Some(Some(Outcome::Win(Player::X))),
+        "0-1" => Some(Some(Outcome::Win(Player::O))),
+        "1/2-1/2" => Some(Some(Outcome::Draw)),
+        _ => text.strip_prefix("X ").and_then(|m| m.parse().ok()).map(|m| Some(Outcome::Margin(m))),
+    }
+}
+
+impl Display for GameRecord {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(seed) = self.seed {
+            writeln!(f, "seed {}", seed)?;
+        }
+        writeln!(f, "result {}", show(&self.result))?;
+        for m in &self.moves {
+            writeln!(f, "move {}", m)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Game, Outcome, Player};
+    use crate::rgu::{Ur, UrMove};
+    use crate::tictactoe::{TicTacToe, TicTacToeMove};
+
+    use super::{GameRecord, ReplayError};
+
+    fn ttt_game() -> GameRecord {
+        let mut record = GameRecord::new(None);
+        for square in [4, 0, 2, 6, 3, 5, 1, 7, 8] {
+            record.push(TicTacToeMove::new(square));
+        }
+        record.result = Some(Outcome::Draw);
+        record
+    }
+
+    #[test]
+    fn roundtrip() {
+        let record = ttt_game();
+        assert_eq!(GameRecord::parse(&record.to_string()), Ok(record.clone()));
+
+        let mut seeded = GameRecord::new(Some(12345));
+        seeded.push(UrMove::Roll(0));
+        seeded.push(UrMove::Pass);
+        let text = seeded.to_string();
+        assert!(text.starts_with("seed 12345\nresult *\n"));
+        assert_eq!(GameRecord::parse(&text), Ok(seeded));
+
+        for result in [Outcome::Win(Player::X), Outcome::Win(Player::O), Outcome::Margin(-3)] {
+            let record = GameRecord { result: Some(result), ..GameRecord::default() };
+            assert_eq!(GameRecord::parse(&record.to_string()).unwrap().result, Some(result));
+        }
+        assert!(GameRecord::parse("seed x").is_err());
+    }
+
+    #[test]
+    fn replay() {
+        let record = ttt_game();
+        let end = record.replay(TicTacToe::new()).unwrap();
+        assert_eq!(end.outcome(), Some(Outcome::Draw));
+
+        let mut illegal = record.clone();
+        illegal.moves[3] = "4".to_string();
+        assert!(matches!(illegal.replay(TicTacToe::new()), Err(ReplayError::IllegalMove { index: 3, .. })));
+
+        let mut wrong = record;
+        wrong.result = Some(Outcome::Win(Player::X));
+        assert!(matches!(wrong.replay(TicTacToe::new()), Err(ReplayError::WrongResult { .. })));
+
+        let mut ur = GameRecord::new(Some(1));
+        ur.push(UrMove::Roll(0));
+        ur.push(UrMove::Pass);
+        assert_eq!(ur.replay(Ur::new()).unwrap().turn(), -1);
+    }
+}
@@ -1,16 +1,21 @@
-use std::{hint::unreachable_unchecked, ops::Neg, fmt::{Display, Formatter, self}};
+use std::{hint::unreachable_unchecked, hash::{Hash, Hasher}, ops::Neg, fmt::{Debug, Display, Formatter, self}, sync::Arc};
 
 use rand::Rng;
 
-use crate::{game::{Game, ToMove, PartiallySolvable, StochasticGame}, iter_bits::IterBits};
+use crate::{chance::{ChanceModel, Dice}, game::{Game, Keyed, ToMove, PartiallySolvable, StochasticGame, Tunable, Outcome, Player}, iter_bits::IterBits, ureval::{Features, UrWeights, FEATURES}, tensor::TensorEncode};
 
 // an implementation of the Royal Game of Ur
 
-const BATTLE_SQUARES: [usize; 7] = [4, 5, 6, 8, 9, 10, 11];
-const ROSETTE_SQUARES: [usize; 3] = [3, 7, 13];
-const FROM_POT: usize = 14;
-const END_SQUARE: usize = 13;
-const STARTING_PIECES: i32 = 7;
+// the length of the longest track, in squares.
+const MAX_TRACK: usize = 16;
+// the heuristic value of a won game, larger than any other evaluation.
+const MATE_SCORE: i32 = 1_000_000;
+// the heuristic value of a position that the value network is sure X wins.
+const NET_SCORE: i32 = MATE_SCORE / 10;
+// the length of the encoding of a position for a value network.
+pub const ENCODED_LEN: usize = 2 * (MAX_TRACK + 2) + 1;
+// the rosette in the middle of the board, on every track.
+const CENTRAL_ROSETTE: (usize, usize) = (1, 3);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(i8)]
@@ -32,73 +37,172 @@ impl Neg for State {
     }
 }
 
+// the route that a player's pieces take around the board. the squares
+// of the route are numbered from 0, and each one is a cell of the board,
+// given as (row, column): row 0 is X's side, row 1 the middle row, and
+// row 2 O's side. O's route is X's, reflected across the middle row.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct Board {
-    // the board is represented as a bitvector
-    // the first 14 bits are the squares for player 1
-    // then, the 14 bits from bit 32 are used 
-    // for player 2's pieces.
-    slots: u64,
+pub enum Track {
+    // Bell's route, as in Finkel's rules: four squares on the player's own
+    // side, the eight squares of the middle row, then back onto the player's
+    // side for the last two.
+    Bell,
+    // Masters' route, over the same board: up the middle row to its second
+    // to last square, across the far end of the opponent's side, and back
+    // through the end of the middle row to finish on the player's own side.
+    Masters,
+    // the Aseb board, with a middle row of twelve squares: four squares on
+    // the player's own side, then the whole of the middle row.
+    Aseb,
 }
 
-impl Board {
-    fn new() -> Board {
-        Board { slots: 0 }
+impl Track {
+    pub fn len(self) -> usize {
+        match self {
+            Track::Bell => 14,
+            Track::Masters | Track::Aseb => 16,
+        }
     }
 
-    fn bits(&self) -> u64 {
-        self.slots
+    // the number of columns the board is drawn with.
+    fn columns(self) -> usize {
+        match self {
+            Track::Bell | Track::Masters => 8,
+            Track::Aseb => 12,
+        }
     }
 
-    fn advance(&mut self, m: Move, player: State) {
-        assert!(player != State::Empty);
-        let big_shift = match player {
-            State::X => 0,
-            State::O => 32,
-            State::Empty => unreachable!()
-        };
-        if m.from != FROM_POT {
-            // we're actually moving a piece, not just putting one
-            // on the board for the first time.
-            let from_bit = 1 << (m.from + big_shift);
-            self.slots &= !from_bit;
+    fn x_cell(self, square: usize) -> (usize, usize) {
+        match (self, square) {
+            (_, 0..=3) => (0, 3 - square),
+            (Track::Bell, 4..=11) => (1, square - 4),
+            (Track::Bell, 12) => (0, 7),
+            (Track::Bell, 13) => (0, 6),
+            (Track::Masters, 4..=10) => (1, square - 4),
+            (Track::Masters, 11) => (2, 6),
+            (Track::Masters, 12) => (2, 7),
+            (Track::Masters, 13) => (1, 7),
+            (Track::Masters, 14) => (0, 7),
+            (Track::Masters, 15) => (0, 6),
+            (Track::Aseb, 4..=15) => (1, square - 4),
+            _ => panic!("square {} is not on the {:?} track", square, self),
         }
-        if m.to > END_SQUARE {
-            // getting off the end of the board
-            return;
+    }
+
+    fn cell(self, player: State, square: usize) -> (usize, usize) {
+        let (row, column) = self.x_cell(square);
+        match player {
+            State::X => (row, column),
+            State::O => (2 - row, column),
+            State::Empty => unreachable!(),
         }
-        let to_bit = 1 << (m.to + big_shift);
-        self.slots |= to_bit;
-        if m.capture {
-            let opposing_spot = 1 << (m.to + (32 - big_shift));
-            self.slots &= !opposing_spot;
+    }
+
+    // the square of the player's route that passes through the cell, if any.
+    fn square_at(self, player: State, cell: (usize, usize)) -> Option<usize> {
+        (0..self.len()).find(|&square| self.cell(player, square) == cell)
+    }
+
+    fn is_rosette(self, cell: (usize, usize)) -> bool {
+        match self {
+            Track::Bell | Track::Masters => matches!(cell, (0, 0) | (2, 0) | (1, 3) | (0, 6) | (2, 6)),
+            // a rosette on every fourth square of the route.
+            Track::Aseb => matches!(cell, (0, 0) | (2, 0) | (1, 3) | (1, 7) | (1, 11)),
         }
     }
+}
 
-    fn revert(&mut self, m: Move, player: State) {
-        assert!(player != State::Empty);
-        let big_shift = match player {
-            State::X => 32,
-            State::O => 0,
-            State::Empty => unreachable!()
-        };
-        if m.from != FROM_POT {
-            // we're actually moving a piece, not just putting one
-            // on the board for the first time.
-            let from_bit = 1 << (m.from + big_shift);
-            self.slots |= from_bit;
+// the rules that vary between the reconstructions of the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UrRules {
+    // the number of pieces each player starts with.
+    pub pieces: u8,
+    // pieces on rosettes can't be captured. only the rosettes on both
+    // players' routes are affected, such as the central one.
+    pub safe_rosettes: bool,
+    // pieces bear off only with the exact roll that takes them one square
+    // past the end of the route, rather than with any roll that reaches it.
+    pub exact_bear_off: bool,
+    // landing on a rosette gives the player another turn.
+    pub rosette_extra_turn: bool,
+    // capturing a piece gives the player another turn.
+    pub capture_extra_turn: bool,
+    pub track: Track,
+}
+
+impl UrRules {
+    // Irving Finkel's rules, on Bell's route.
+    pub fn finkel() -> Self {
+        Self {
+            pieces: 7,
+            safe_rosettes: true,
+            exact_bear_off: true,
+            rosette_extra_turn: true,
+            capture_extra_turn: false,
+            track: Track::Bell,
+        }
+    }
+
+    // James Masters' rules, on his longer route, where rosettes are not safe.
+    pub fn masters() -> Self {
+        Self {
+            safe_rosettes: false,
+            track: Track::Masters,
+            ..Self::finkel()
+        }
+    }
+
+    // a quicker game on Masters' route, with five pieces, and another turn for a capture.
+    pub fn blitz() -> Self {
+        Self {
+            pieces: 5,
+            capture_extra_turn: true,
+            ..Self::masters()
         }
-        if m.to > END_SQUARE {
-            // getting off the end of the board
-            return;
+    }
+
+    // the game of Aseb, with five pieces.
+    pub fn aseb() -> Self {
+        Self {
+            pieces: 5,
+            track: Track::Aseb,
+            ..Self::finkel()
         }
-        let to_bit = 1 << (m.to + big_shift);
-        self.slots &= !to_bit;
-        if m.capture {
-            let opposing_spot = 1 << (m.to + (32 - big_shift));
-            self.slots |= opposing_spot;
+    }
+}
+
+impl Default for UrRules {
+    // Finkel's rules on Bell's route, with safe rosettes, but a piece may
+    // bear off with any roll that takes it past the end of the route.
+    fn default() -> Self {
+        Self {
+            pieces: 7,
+            safe_rosettes: true,
+            exact_bear_off: false,
+            rosette_extra_turn: true,
+            capture_extra_turn: false,
+            track: Track::Bell,
         }
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Board {
+    // the board is represented as a bitvector
+    // the first 16 bits are the squares of player 1's route
+    // then, the 16 bits from bit 32 are used 
+    // for player 2's route.
+    slots: u64,
+}
+
+impl Board {
+    fn new() -> Board {
+        Board { slots: 0 }
+    }
+
+    fn bits(&self) -> u64 {
+        self.slots
+    }
 
     fn is_empty(&self) -> bool {
         self.slots == 0
@@ -146,38 +250,25 @@ impl Board {
         self.slots |= bit;
     }
 
-    fn count(&self, player: State) -> u32 {
+    fn clear(&mut self, slot: usize, player: State) {
         assert!(player != State::Empty);
         let big_shift = match player {
             State::X => 0,
             State::O => 32,
             State::Empty => unsafe { unreachable_unchecked() }
         };
-        ((self.slots >> big_shift) & 0xFFFF_FFFF).count_ones()
-    }
-
-    fn char_at(&self, slot: usize) -> char {
-        if self.test(slot, State::X) {
-            'X'
-        } else if self.test(slot, State::O) {
-            'O'
-        } else {
-            '.'
-        }
+        let bit = 1 << (slot + big_shift);
+        self.slots &= !bit;
     }
 
-    fn chars_at(&self, slot: usize) -> (char, char) {
-        let left = if self.test(slot, State::X) {
-            'X'
-        } else {
-            '.'
-        };
-        let right = if self.test(slot, State::O) {
-            'O'
-        } else {
-            '.'
+    fn count(&self, player: State) -> u32 {
+        assert!(player != State::Empty);
+        let big_shift = match player {
+            State::X => 0,
+            State::O => 32,
+            State::Empty => unsafe { unreachable_unchecked() }
         };
-        (left, right)
+        ((self.slots >> big_shift) & 0xFFFF_FFFF).count_ones()
     }
 }
 
@@ -187,9 +278,72 @@ pub struct Ur {
     pots: [u8; 2],
     moves: usize,
     last_roll: Option<usize>,
-    rolls: Vec<usize>,
+    // the moves played so far, other than rolls, each with its roll.
+    played: Vec<(UrMove, usize)>,
+    dice: UrDice,
+    rules: UrRules,
+    weights: UrWeights,
+    // a value network that stands in for the weighted features, if set.
+    net: Option<SharedNet>,
+}
+
+// the dice, with their outcomes worked out once, as the heuristic weighs
+// them at every leaf of a search. two sets are the same if the dice are.
+#[derive(Debug, Clone)]
+struct UrDice {
+    dice: Dice,
+    outcomes: Arc<[(usize, f64)]>,
+}
+
+impl UrDice {
+    fn new(dice: Dice) -> Self {
+        let outcomes = dice.outcomes().into();
+        Self { dice, outcomes }
+    }
+}
+
+impl PartialEq for UrDice {
+    fn eq(&self, other: &Self) -> bool {
+        self.dice == other.dice
+    }
+}
+
+impl Eq for UrDice {}
+
+impl Hash for UrDice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.dice.hash(state);
+    }
+}
+
+// a value function that can stand in for the heuristic: the chance that X
+// wins, from the position as encode() gives it.
+pub trait ValueNet: Debug + Send + Sync {
+    fn forward(&self, inputs: &[f64]) -> f64;
+}
+
+// a network shared between positions. positions compare equal, and hash
+// alike, when they share the same network, so it doesn't get in the way of
+// comparing or hashing them.
+#[derive(Debug, Clone)]
+struct SharedNet(Arc<dyn ValueNet>);
+
+impl PartialEq for SharedNet {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(Arc::as_ptr(&self.0), Arc::as_ptr(&other.0))
+    }
 }
 
+impl Eq for SharedNet {}
+
+impl Hash for SharedNet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<()>().hash(state);
+    }
+}
+
+// a move of a piece to another square of its route. a `from` of the
+// route's length means the piece enters from the pot.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Move {
     from: usize,
@@ -200,34 +354,360 @@ pub struct Move {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UrMove {
     Move(Move),
+    // the piece on this square leaves the board at the end of its route.
+    BearOff(usize),
     Roll(usize),
     Pass,
 }
 
+// why a move can't be played in a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrMoveError {
+    GameOver,
+    // a piece was moved, or the turn passed, before the dice were rolled.
+    NotRolled,
+    AlreadyRolled,
+    // the dice can't come up with this roll.
+    ImpossibleRoll(usize),
+    // there is no piece of ours on the square, or none in the pot.
+    NoPiece(usize),
+    // the piece doesn't move as far as the roll takes it.
+    WrongDistance { roll: usize },
+    // the square holds one of our pieces, or one of theirs on a safe rosette.
+    Blocked(usize),
+    // the move claims a capture where there's nothing to capture, or the reverse.
+    WrongCapture,
+    // the turn was passed when a piece could move.
+    MustMove,
+}
+
+impl Display for UrMoveError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            UrMoveError::GameOver => write!(f, "the game is over"),
+            UrMoveError::NotRolled => write!(f, "the dice haven't been rolled"),
+            UrMoveError::AlreadyRolled => write!(f, "the dice have already been rolled"),
+            UrMoveError::ImpossibleRoll(roll) => write!(f, "the dice can't roll {}", roll),
+            UrMoveError::NoPiece(square) => write!(f, "no piece to move on square {}", square),
+            UrMoveError::WrongDistance { roll } => write!(f, "the piece must move {} squares", roll),
+            UrMoveError::Blocked(square) => write!(f, "square {} is blocked", square),
+            UrMoveError::WrongCapture => write!(f, "the capture doesn't match the board"),
+            UrMoveError::MustMove => write!(f, "can't pass with a move available"),
+        }
+    }
+}
+
 impl Ur {
     pub fn new() -> Self {
+        Self::with_rules(UrRules::default())
+    }
+
+    pub fn with_rules(rules: UrRules) -> Self {
         Self {
             slots: Board::new(),
             moves: 0,
-            pots: [STARTING_PIECES as u8; 2],
+            pots: [rules.pieces; 2],
             last_roll: None,
-            rolls: Vec::new(),
+            played: Vec::new(),
+            dice: UrDice::new(Dice::Binary(4)),
+            rules,
+            weights: UrWeights::default(),
+            net: None,
         }
     }
 
-    fn dice_roll() -> usize {
-        // Movements are determined by rolling a set of four-sided, tetrahedron-shaped dice.
-        // Two of the four corners of each die are marked and the other two are not, 
-        // giving each die an equal chance of landing with a marked or unmarked corner facing up.
-        // The number of marked ends facing upwards after a roll of the dice indicates how many
-        // spaces a player may move during that turn.
+    // the game with other dice, such as a d6, in place of the four binary dice.
+    pub fn with_dice(dice: Dice) -> Self {
+        Self {
+            dice: UrDice::new(dice),
+            ..Self::new()
+        }
+    }
 
-        let mut rng = rand::thread_rng();
-        let mut sum = 0;
-        for _ in 0..4 {
-            sum += rng.gen_bool(0.5) as usize;
+    // a position where X and O have pieces on the given squares and none in
+    // their pots, and every other piece has been borne off. it is `to_move`'s
+    // turn to roll.
+    pub fn endgame(x: &[usize], o: &[usize], to_move: Player) -> Self {
+        let rules = UrRules::default();
+        assert!(x.len() <= rules.pieces as usize && o.len() <= rules.pieces as usize);
+        let mut slots = Board::new();
+        for &square in x {
+            assert!(square < rules.track.len());
+            slots.set(square, State::X);
+        }
+        for &square in o {
+            assert!(square < rules.track.len());
+            slots.set(square, State::O);
+        }
+        Self {
+            slots,
+            pots: [0, 0],
+            moves: if to_move == Player::X { 0 } else { 1 },
+            last_roll: None,
+            played: Vec::new(),
+            dice: UrDice::new(Dice::Binary(4)),
+            rules,
+            weights: UrWeights::default(),
+            net: None,
+        }
+    }
+
+    pub fn rules(&self) -> UrRules {
+        self.rules
+    }
+
+    // the position evaluated with other weights for the heuristic's features.
+    pub fn with_weights(self, weights: UrWeights) -> Self {
+        Self { weights, ..self }
+    }
+
+    // the position judged by a value network instead of the weighted features.
+    pub fn with_net(self, net: Arc<dyn ValueNet>) -> Self {
+        Self { net: Some(SharedNet(net)), ..self }
+    }
+
+    pub fn without_net(self) -> Self {
+        Self { net: None, ..self }
+    }
+
+    // the position as the inputs of a value network: for X and then O, a one
+    // for each square of the route that holds a piece, and the fractions of
+    // the pieces in the pot and borne off; and last, a one if X is to move.
+    pub fn encode(&self) -> Vec<f64> {
+        let mut inputs = Vec::with_capacity(ENCODED_LEN);
+        inputs.extend(self.side_inputs(State::X));
+        inputs.extend(self.side_inputs(State::O));
+        inputs.push(if self.side() == State::X { 1.0 } else { 0.0 });
+        inputs
+    }
+
+    // a one for each square of the player's route that holds one of their
+    // pieces, then the fractions of their pieces in the pot and borne off.
+    fn side_inputs(&self, player: State) -> impl Iterator<Item = f64> + '_ {
+        let pieces = self.rules.pieces as f64;
+        (0..MAX_TRACK)
+            .map(move |square| if square < self.end() && self.slots.test(square, player) { 1.0 } else { 0.0 })
+            .chain([self.pot(player) as f64 / pieces, self.borne_off(player) as f64 / pieces])
+    }
+
+    // the features of the position that the heuristic weighs, for X less O.
+    pub fn features(&self) -> Features {
+        let outcomes = &self.dice.outcomes;
+        let mut features = [0.0; FEATURES.len()];
+        for (player, sign) in [(State::X, 1.0), (State::O, -1.0)] {
+            for (feature, value) in features.iter_mut().zip(self.side_features(player, outcomes)) {
+                *feature += sign * value;
+            }
+        }
+        features
+    }
+
+    fn side_features(&self, us: State, outcomes: &[(usize, f64)]) -> Features {
+        let track = self.rules.track;
+        let mut progress = 0.0;
+        let mut rosettes = 0.0;
+        let mut threatened = 0.0;
+        let mut central = 0.0;
+        for square in self.slots.filled_slots(us) {
+            let cell = track.cell(us, square);
+            progress += square as f64;
+            if track.is_rosette(cell) {
+                rosettes += 1.0;
+            }
+            if cell == CENTRAL_ROSETTE {
+                central += 1.0;
+            }
+            threatened += self.threat(us, square, outcomes);
+        }
+        [progress, self.pot(us) as f64, self.borne_off(us) as f64, rosettes, threatened, central]
+    }
+
+    // the chance that the opponent's next roll lets them capture our piece on
+    // `square`, from the board or from the pot. whether the capturing piece
+    // would be blocked doesn't matter, as it can't be: the square is ours.
+    fn threat(&self, us: State, square: usize, outcomes: &[(usize, f64)]) -> f64 {
+        let track = self.rules.track;
+        let cell = track.cell(us, square);
+        let Some(theirs) = track.square_at(-us, cell) else {
+            return 0.0;
+        };
+        if track.is_rosette(cell) && self.rules.safe_rosettes {
+            return 0.0;
+        }
+        outcomes
+            .iter()
+            .filter(|&&(roll, _)| {
+                let from_board = roll > 0 && roll <= theirs && self.slots.test(theirs - roll, -us);
+                let from_pot = roll == theirs + 1 && self.pot(-us) > 0;
+                from_board || from_pot
+            })
+            .map(|&(_, prob)| prob)
+            .sum()
+    }
+
+    fn pot(&self, player: State) -> u8 {
+        self.pots[if player == State::X { 0 } else { 1 }]
+    }
+
+    pub fn dice_roll<R: Rng>(&self, rng: &mut R) -> usize {
+        // the roll is however many squares the player may move this turn.
+        // with the usual dice, four tetrahedra with two of their four corners
+        // marked, that's the number of marked corners facing up.
+        self.dice.dice.sample(rng)
+    }
+
+    fn side(&self) -> State {
+        if self.turn() == 1 { State::X } else { State::O }
+    }
+
+    // the square past the end of the route, where borne off pieces go,
+    // which also stands for the pot in a move's `from`.
+    fn end(&self) -> usize {
+        self.rules.track.len()
+    }
+
+    // the opponent's square under our square `to`, if our route shares it.
+    fn opposing_square(&self, us: State, to: usize) -> Option<usize> {
+        let track = self.rules.track;
+        track.square_at(-us, track.cell(us, to))
+    }
+
+    // whether our piece can move to `to`, on the board, and if so, whether it captures.
+    fn destination(&self, us: State, to: usize) -> Option<bool> {
+        if self.slots.test(to, us) {
+            return None;
+        }
+        match self.opposing_square(us, to) {
+            Some(theirs) if self.slots.test(theirs, -us) => {
+                let rosette = self.rules.track.is_rosette(self.rules.track.cell(us, to));
+                if rosette && self.rules.safe_rosettes {
+                    None
+                } else {
+                    Some(true)
+                }
+            },
+            _ => Some(false),
+        }
+    }
+
+    fn earns_extra_turn(&self, m: Move) -> bool {
+        // the rosettes are placed symmetrically, so it doesn't
+        // matter whose route the square is looked up on.
+        let track = self.rules.track;
+        let rosette = track.is_rosette(track.x_cell(m.to));
+        (rosette && self.rules.rosette_extra_turn) || (m.capture && self.rules.capture_extra_turn)
+    }
+
+    // what's in a cell of the board: a piece, Empty, or None for
+    // a cell that isn't on either route.
+    fn piece_at(&self, cell: (usize, usize)) -> Option<State> {
+        let track = self.rules.track;
+        let x = track.square_at(State::X, cell);
+        let o = track.square_at(State::O, cell);
+        if x.is_some_and(|square| self.slots.test(square, State::X)) {
+            Some(State::X)
+        } else if o.is_some_and(|square| self.slots.test(square, State::O)) {
+            Some(State::O)
+        } else if x.is_some() || o.is_some() {
+            Some(State::Empty)
+        } else {
+            None
+        }
+    }
+
+    // the number of the player's pieces that have been borne off.
+    fn borne_off(&self, player: State) -> u32 {
+        self.rules.pieces as u32 - self.pot(player) as u32 - self.slots.count(player)
+    }
+
+    // the last move played, other than a roll, and who played it.
+    fn last_move(&self) -> Option<(State, UrMove)> {
+        let &(m, _) = self.played.last()?;
+        // the side to move is the one that played it
+        // only if the move earned another turn.
+        let again = match m {
+            UrMove::Move(m) => self.earns_extra_turn(m),
+            _ => false,
+        };
+        Some((if again { self.side() } else { -self.side() }, m))
+    }
+
+    // a fuller drawing of the position than Display's, for playing at the terminal.
+    pub fn rendering(&self) -> Rendering<'_> {
+        Rendering { game: self, colour: false }
+    }
+
+    pub fn is_legal(&self, m: UrMove) -> bool {
+        self.check(m).is_ok()
+    }
+
+    // plays the move if it's legal, and otherwise says why not,
+    // leaving the position as it was.
+    pub fn try_push(&mut self, m: UrMove) -> Result<(), UrMoveError> {
+        self.check(m)?;
+        self.push(m);
+        Ok(())
+    }
+
+    fn check(&self, m: UrMove) -> Result<(), UrMoveError> {
+        if self.outcome().is_some() {
+            return Err(UrMoveError::GameOver);
+        }
+        let roll = match (self.last_roll, m) {
+            (None, UrMove::Roll(roll)) => {
+                return match self.dice.outcomes.iter().any(|&(r, _)| r == roll) {
+                    true => Ok(()),
+                    false => Err(UrMoveError::ImpossibleRoll(roll)),
+                };
+            },
+            (None, _) => return Err(UrMoveError::NotRolled),
+            (Some(_), UrMove::Roll(_)) => return Err(UrMoveError::AlreadyRolled),
+            (Some(roll), _) => roll,
+        };
+        let us = self.side();
+        let end = self.end();
+        match m {
+            UrMove::Move(m) => {
+                let to = if m.from == end {
+                    if self.pots[self.moves & 1] == 0 {
+                        return Err(UrMoveError::NoPiece(m.from));
+                    }
+                    roll.checked_sub(1)
+                } else {
+                    if m.from > end || !self.slots.test(m.from, us) {
+                        return Err(UrMoveError::NoPiece(m.from));
+                    }
+                    Some(m.from + roll)
+                };
+                if roll == 0 || to != Some(m.to) || m.to >= end {
+                    return Err(UrMoveError::WrongDistance { roll });
+                }
+                match self.destination(us, m.to) {
+                    None => Err(UrMoveError::Blocked(m.to)),
+                    Some(capture) if capture != m.capture => Err(UrMoveError::WrongCapture),
+                    Some(_) => Ok(()),
+                }
+            },
+            UrMove::BearOff(from) => {
+                if from >= end || !self.slots.test(from, us) {
+                    return Err(UrMoveError::NoPiece(from));
+                }
+                match (from + roll).cmp(&end) {
+                    std::cmp::Ordering::Equal => Ok(()),
+                    std::cmp::Ordering::Greater if !self.rules.exact_bear_off => Ok(()),
+                    _ => Err(UrMoveError::WrongDistance { roll }),
+                }
+            },
+            UrMove::Pass => {
+                let mut moves = Vec::new();
+                self.generate_moves(&mut moves);
+                match moves[..] {
+                    [UrMove::Pass] => Ok(()),
+                    _ => Err(UrMoveError::MustMove),
+                }
+            },
+            UrMove::Roll(_) => unreachable!(),
         }
-        sum
     }
 }
 
@@ -235,6 +715,7 @@ impl Display for UrMove {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
             UrMove::Move(m) => write!(f, "{}-{}", m.from, m.to),
+            UrMove::BearOff(from) => write!(f, "{}-off", from),
             UrMove::Roll(r) => write!(f, "roll {}", r),
             UrMove::Pass => write!(f, "pass"),
         }
@@ -252,59 +733,59 @@ impl Game for Ur {
         }
     }
 
-    fn evaluate(&self) -> i8 {
+    fn outcome(&self) -> Option<Outcome> {
+        // the first player to bear off all their pieces wins.
         if self.pots[0] == 0 && self.slots.is_side_empty(State::X) {
-            1
+            Some(Outcome::Win(Player::X))
         } else if self.pots[1] == 0 && self.slots.is_side_empty(State::O) {
-            -1
+            Some(Outcome::Win(Player::O))
         } else {
-            0
+            None
         }
     }
 
-    fn is_terminal(&self) -> bool {
-        self.evaluate() != 0
-    }
-
     fn generate_moves(&self, buffer: &mut Vec<Self::Move>) {
         match self.last_roll {
             Some(roll) => {
-                if roll == 0 {
-                    // add a pass if we can't do anything
-                    if buffer.is_empty() {
-                        buffer.push(UrMove::Pass);
-                    }
-                    return;
-                }
-                let us = if self.turn() == 1 { State::X } else { State::O };
+                let start = buffer.len();
+                let us = self.side();
+                let end = self.end();
                 // add all the moves of pieces on the board
                 for from in self.slots.filled_slots(us) {
-                    let to = std::cmp::min(from + roll, 14);
-                    if !self.slots.test(to, us) {
-                        let capture = self.slots.test(to, -us);
-                        buffer.push(UrMove::Move(Move {
-                            from,
-                            to,
-                            capture,
-                        }));
+                    let to = from + roll;
+                    if to < end {
+                        if let Some(capture) = self.destination(us, to) {
+                            buffer.push(UrMove::Move(Move {
+                                from,
+                                to,
+                                capture,
+                            }));
+                        }
+                    } else if to == end || !self.rules.exact_bear_off {
+                        buffer.push(UrMove::BearOff(from));
                     }
                 }
                 // add a move for adding a new piece
-                if self.pots[self.moves & 1] > 0 && roll != 0 {
+                if self.pots[self.moves & 1] > 0 && roll > 0 && roll < end {
                     let to = roll - 1;
-                    if !self.slots.test(to, us) {
+                    if let Some(capture) = self.destination(us, to) {
                         buffer.push(UrMove::Move(Move {
-                            from: FROM_POT,
+                            from: end,
                             to,
-                            capture: false,
+                            capture,
                         }));
                     }
                 }
+                // a player who can't move, with a roll of zero or with
+                // every piece blocked, passes the turn.
+                if buffer.len() == start {
+                    buffer.push(UrMove::Pass);
+                }
             },
             None => {
                 // add the roll moves
-                for i in 0..=4 {
-                    buffer.push(UrMove::Roll(i));
+                for &(roll, _) in self.dice.outcomes.iter() {
+                    buffer.push(UrMove::Roll(roll));
                 }
             },
         }
@@ -313,22 +794,31 @@ impl Game for Ur {
     fn push(&mut self, m: Self::Move) {
         match m {
             UrMove::Move(m) => {
-                let us = self.turn();
-                let us = if us == 1 { State::X } else { State::O };
-                self.slots.advance(m, us);
+                let us = self.side();
+                let end = self.end();
+                if m.from == end {
+                    self.pots[self.moves & 1] -= 1;
+                } else {
+                    self.slots.clear(m.from, us);
+                }
+                self.slots.set(m.to, us);
                 if m.capture {
+                    let theirs = self.opposing_square(us, m.to).expect("captures are on shared squares");
+                    self.slots.clear(theirs, -us);
                     self.pots[(self.moves & 1) ^ 1] += 1;
                 }
-                if m.from == FROM_POT {
-                    self.pots[self.moves & 1] -= 1;
-                }
-                let landed_on_rosette = ROSETTE_SQUARES.contains(&m.to);
-                if !landed_on_rosette {
-                    // we don't count the move if we landed on a rosette
-                    // so the player gets another turn.
+                if !self.earns_extra_turn(m) {
+                    // we don't count the move if the player
+                    // gets another turn, such as on a rosette.
                     self.moves += 1;
                 }
-                self.rolls.push(self.last_roll.unwrap());
+                self.played.push((UrMove::Move(m), self.last_roll.unwrap()));
+                self.last_roll = None;
+            },
+            UrMove::BearOff(from) => {
+                self.slots.clear(from, self.side());
+                self.moves += 1;
+                self.played.push((m, self.last_roll.unwrap()));
                 self.last_roll = None;
             },
             UrMove::Roll(roll) => {
@@ -336,7 +826,7 @@ impl Game for Ur {
             },
             UrMove::Pass => {
                 self.moves += 1;
-                self.rolls.push(self.last_roll.unwrap());
+                self.played.push((m, self.last_roll.unwrap()));
                 self.last_roll = None;
             },
         }
@@ -345,35 +835,56 @@ impl Game for Ur {
     fn pop(&mut self, m: Self::Move) {
         match m {
             UrMove::Move(m) => {
-                let landed_on_rosette = ROSETTE_SQUARES.contains(&m.to);
-                if !landed_on_rosette {
-                    // we don't count the move if we landed on a rosette
-                    // so the player gets another turn.
+                if !self.earns_extra_turn(m) {
+                    // we don't count the move if the player
+                    // gets another turn, such as on a rosette.
                     self.moves -= 1;
                 }
-                let us = self.turn();
-                let us = if us == 1 { State::O } else { State::X };
-                self.slots.revert(m, us);
+                let us = self.side();
+                let end = self.end();
+                self.slots.clear(m.to, us);
+                if m.from == end {
+                    self.pots[self.moves & 1] += 1;
+                } else {
+                    self.slots.set(m.from, us);
+                }
                 if m.capture {
+                    let theirs = self.opposing_square(us, m.to).expect("captures are on shared squares");
+                    self.slots.set(theirs, -us);
                     self.pots[(self.moves & 1) ^ 1] -= 1;
                 }
-                if m.from == FROM_POT {
-                    self.pots[self.moves & 1] += 1;
-                }
-                self.last_roll = self.rolls.pop();
+                self.last_roll = self.played.pop().map(|(_, roll)| roll);
+            },
+            UrMove::BearOff(from) => {
+                self.moves -= 1;
+                self.slots.set(from, self.side());
+                self.last_roll = self.played.pop().map(|(_, roll)| roll);
             },
             UrMove::Roll(_) => {
                 self.last_roll = None;
             },
             UrMove::Pass => {
                 self.moves -= 1;
-                self.last_roll = self.rolls.pop();
+                self.last_roll = self.played.pop().map(|(_, roll)| roll);
             },
         }
     }
 
     fn action_space_size(&self) -> usize {
-        21
+        MAX_TRACK + 2 + self.dice.dice.max_outcome() + 1
+    }
+
+    fn move_index(&self, m: Self::Move) -> usize {
+        // the pass takes 0, moves are indexed by their starting square
+        // (or the pot) from 1, and the rolls follow, as many as the dice have.
+        // a piece can't both bear off and stay on the board with one roll,
+        // so bearing off shares its square's index.
+        match m {
+            UrMove::Pass => 0,
+            UrMove::Move(m) => 1 + m.from,
+            UrMove::BearOff(from) => 1 + from,
+            UrMove::Roll(r) => MAX_TRACK + 2 + r,
+        }
     }
 
     fn to_move(&self) -> ToMove {
@@ -392,65 +903,117 @@ impl Game for Ur {
 
 impl PartiallySolvable for Ur {
     fn heuristic(&self) -> i32 {
-        const MATE_SCORE: i32 = 1_000_000;
-        const IN_POT_PENALTY: i32 = 50;
-        const PROGRESS_SCORE: i32 = 100;
-        const FINISHING_SCORE: i32 = 2000;
-
-        if self.is_terminal() {
-            return self.evaluate() as i32 * MATE_SCORE;
+        if let Some(outcome) = self.outcome() {
+            return outcome.sign() as i32 * MATE_SCORE;
         }
 
-        // it's good to have pieces further forward,
-        // and it's good to have more pieces on the board
-        // as you will have more choices to make.
-        // it's even better to have moved pieces off the board.
-        let mut score = 0;
-        for x_pos in self.slots.filled_slots(State::X) {
-            score += x_pos as i32 * PROGRESS_SCORE;
+        // the network's estimate of X's chances, from -NET_SCORE for a
+        // sure loss up to NET_SCORE for a sure win.
+        if let Some(SharedNet(net)) = &self.net {
+            return ((2.0 * net.forward(&self.encode()) - 1.0) * NET_SCORE as f64).round() as i32;
         }
-        for o_pos in self.slots.filled_slots(State::O) {
-            score -= o_pos as i32 * PROGRESS_SCORE;
+
+        // a weighted sum of the features, which has to stay short of a won
+        // game however the weights have been set.
+        let value = self.weights.evaluate(&self.features()).round() as i32;
+        value.clamp(-MATE_SCORE + 1, MATE_SCORE - 1)
+    }
+
+    fn heuristic_bounds(&self) -> (i32, i32) {
+        (-MATE_SCORE, MATE_SCORE)
+    }
+}
+
+impl Tunable for Ur {
+    fn feature_names(&self) -> &'static [&'static str] {
+        &FEATURES
+    }
+
+    fn feature_vector(&self) -> Vec<f64> {
+        self.features().to_vec()
+    }
+
+    fn weights(&self) -> Vec<f64> {
+        self.weights.0.iter().map(|&w| w as f64).collect()
+    }
+
+    fn set_weights(&mut self, weights: &[f64]) {
+        assert_eq!(weights.len(), FEATURES.len());
+        for (mine, &weight) in self.weights.0.iter_mut().zip(weights) {
+            *mine = weight.round() as i32;
         }
-        score -= self.pots[0] as i32 * IN_POT_PENALTY;
-        score += self.pots[1] as i32 * IN_POT_PENALTY;
-        let total_xs = self.slots.count(State::X) as i32 + self.pots[0] as i32;
-        let total_os = self.slots.count(State::O) as i32 + self.pots[1] as i32;
-        score += (STARTING_PIECES - total_xs) * FINISHING_SCORE;
-        score -= (STARTING_PIECES - total_os) * FINISHING_SCORE;
-        score
+    }
+
+    fn save_weights(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.weights.save(path)
+    }
+}
+
+impl TensorEncode for Ur {
+    fn tensor_shape(&self) -> Vec<usize> {
+        vec![2 * (MAX_TRACK + 2) + self.dice.dice.max_outcome() + 1]
+    }
+
+    fn encode_tensor(&self) -> Vec<f32> {
+        // the side to move's pieces and then the opponent's, as for a value
+        // network, and a one for the roll waiting to be played, if there is one.
+        let us = self.side();
+        let mut tensor = self.side_inputs(us).chain(self.side_inputs(-us)).map(|x| x as f32).collect::<Vec<_>>();
+        tensor.extend((0..=self.dice.dice.max_outcome()).map(|roll| (self.last_roll == Some(roll)) as u8 as f32));
+        tensor
+    }
+}
+
+impl Keyed for Ur {
+    fn hashkey(&self) -> u64 {
+        // only the state that matters for the rest of the game goes in the key:
+        // the board, the pots, the side to move and the roll waiting to be played.
+        // the move count and the moves played are history, so positions
+        // reached by different routes share a key.
+        // the board uses bits 0..16 and 32..48, leaving the gaps free.
+        let pots = (self.pots[0] as u64) << 16 | (self.pots[1] as u64) << 20;
+        let side = ((self.moves & 1) as u64) << 24;
+        let roll = self.last_roll.map_or(0, |r| r as u64 + 1) << 48;
+        self.slots.bits() | pots | side | roll
     }
 }
 
 impl StochasticGame for Ur {
-    fn generate_legal_moves_with_probabilities(&self, buffer: &mut Vec<(Self::Move, f32)>) {
-        assert!(self.last_roll.is_none(), "generate_legal_moves_with_probabilities called on a non-chance node");
-        const MOVE_PROBS: [(UrMove, f32); 5] = [
-            (UrMove::Roll(0), 0.0625),
-            (UrMove::Roll(1), 0.2500),
-            (UrMove::Roll(2), 0.3750),
-            (UrMove::Roll(3), 0.2500),
-            (UrMove::Roll(4), 0.0625),
-        ];
-        buffer.extend_from_slice(&MOVE_PROBS);
+    type Chance = Dice;
+
+    fn chance_model(&self) -> &Dice {
+        &self.dice.dice
+    }
+
+    fn chance_move(&self, roll: usize) -> UrMove {
+        UrMove::Roll(roll)
+    }
+
+    // the rolls from the outcomes worked out when the dice were set.
+    fn generate_legal_moves_with_probabilities(&self, buffer: &mut Vec<(UrMove, f64)>) {
+        assert_eq!(self.to_move(), ToMove::Chance, "generate_legal_moves_with_probabilities called on a non-chance node");
+        buffer.extend(self.dice.outcomes.iter().map(|&(roll, prob)| (UrMove::Roll(roll), prob)));
     }
 }
 
 impl Display for Ur {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        // the first line is x3 -> x0, then x13 -> x12.
-        // the second line is 4 -> 11.
-        // the third line is o3 -> o0, then o13 -> o12.
+        // the board is drawn as it lies, X's side on top,
+        // and cells that aren't on the board left blank.
         // then we'll do dice roll and pots.
-        writeln!(f, "{} {} {} {}     {} {}",
-            self.slots.chars_at(3).0, self.slots.chars_at(2).0, self.slots.chars_at(1).0, self.slots.chars_at(0).0, self.slots.chars_at(13).0, self.slots.chars_at(12).0,
-        )?;
-        writeln!(f, "{} {} {} {} {} {} {} {}",
-            self.slots.char_at(4), self.slots.char_at(5), self.slots.char_at(6), self.slots.char_at(7), self.slots.char_at(8), self.slots.char_at(9), self.slots.char_at(10), self.slots.char_at(11),
-        )?;
-        writeln!(f, "{} {} {} {}     {} {}",
-            self.slots.chars_at(3).1, self.slots.chars_at(2).1, self.slots.chars_at(1).1, self.slots.chars_at(0).1, self.slots.chars_at(13).1, self.slots.chars_at(12).1,
-        )?;
+        let track = self.rules.track;
+        for row in 0..3 {
+            let cells = (0..track.columns())
+                .map(|column| match self.piece_at((row, column)) {
+                    Some(State::X) => 'X',
+                    Some(State::O) => 'O',
+                    Some(State::Empty) => '.',
+                    None => ' ',
+                })
+                .map(String::from)
+                .collect::<Vec<_>>();
+            writeln!(f, "{}", cells.join(" "))?;
+        }
         writeln!(f)?;
         writeln!(f, "Move {}", self.moves + 1)?;
         match self.last_roll {
@@ -462,6 +1025,88 @@ impl Display for Ur {
     }
 }
 
+// the position drawn with rosettes marked as '*', and each cell labelled
+// with its square on the route of the side to move, as written in moves,
+// so the labels also count up the way that side's pieces travel. the cells
+// of the last move are bracketed, and the pots and borne off pieces are
+// counted for each side. with colour on, ANSI escapes pick out the pieces,
+// the rosettes and the last move.
+pub struct Rendering<'a> {
+    game: &'a Ur,
+    colour: bool,
+}
+
+impl Rendering<'_> {
+    pub fn with_colour(self, colour: bool) -> Self {
+        Self { colour, ..self }
+    }
+
+    fn paint(&self, text: String, code: &str) -> String {
+        if self.colour && !code.is_empty() {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text
+        }
+    }
+
+    fn name(player: State) -> &'static str {
+        if player == State::X { "X" } else { "O" }
+    }
+}
+
+impl Display for Rendering<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let game = self.game;
+        let track = game.rules.track;
+        let us = game.side();
+        let last = game.last_move();
+        // the cells the last move left and arrived at.
+        let highlighted = match last {
+            Some((player, UrMove::Move(m))) if m.from == game.end() => vec![track.cell(player, m.to)],
+            Some((player, UrMove::Move(m))) => vec![track.cell(player, m.from), track.cell(player, m.to)],
+            Some((player, UrMove::BearOff(from))) => vec![track.cell(player, from)],
+            _ => vec![],
+        };
+        for row in 0..3 {
+            let mut line = String::new();
+            for column in 0..track.columns() {
+                let cell = (row, column);
+                let Some(piece) = game.piece_at(cell) else {
+                    line.push_str("     ");
+                    continue;
+                };
+                let (mark, code) = match piece {
+                    State::X => ('X', "1;33"),
+                    State::O => ('O', "1;36"),
+                    State::Empty if track.is_rosette(cell) => ('*', "35"),
+                    State::Empty => ('.', ""),
+                };
+                let label = track.square_at(us, cell).map_or("  ".to_string(), |square| format!("{:>2}", square));
+                let text = if highlighted.contains(&cell) {
+                    // reversed, as well as in the mark's colour.
+                    let code = if code.is_empty() { "7".to_string() } else { format!("7;{}", code) };
+                    self.paint(format!("[{}{}]", mark, label), &code)
+                } else {
+                    format!(" {}{} ", self.paint(mark.to_string(), code), label)
+                };
+                line.push_str(&text);
+            }
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        writeln!(f)?;
+        for player in [State::X, State::O] {
+            writeln!(f, "{}: {} to enter, {} borne off", Self::name(player), game.pot(player), game.borne_off(player))?;
+        }
+        if let Some((player, m)) = last {
+            writeln!(f, "last move: {} {}", Self::name(player), m)?;
+        }
+        match game.last_roll {
+            Some(roll) => writeln!(f, "{} rolled {}, to move", Self::name(us), roll),
+            None => writeln!(f, "{} to roll", Self::name(us)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::perft::perft;
@@ -574,6 +1219,138 @@ Roll: ? | Pots: 6 X, 7 O
         }
     }
 
+    #[test]
+    fn hashkey_ignores_history() {
+        use super::*;
+        let start = Ur::new();
+        let mut g = Ur::new();
+        // both sides roll a zero and pass, which leaves the position as it was.
+        for m in [UrMove::Roll(0), UrMove::Pass, UrMove::Roll(0), UrMove::Pass] {
+            g.push(m);
+        }
+        assert_ne!(g, start);
+        assert_eq!(g.hashkey(), start.hashkey());
+
+        // but the side to move and the pending roll are part of the position.
+        g.push(UrMove::Roll(0));
+        assert_ne!(g.hashkey(), start.hashkey());
+        g.push(UrMove::Pass);
+        assert_ne!(g.hashkey(), start.hashkey());
+    }
+
+    // a chance node where `player` has one piece left, on `square`,
+    // and the other side still has every piece in its pot.
+    fn last_piece(player: super::State, square: usize) -> super::Ur {
+        use super::*;
+        let mut slots = Board::new();
+        slots.set(square, player);
+        let pots = if player == State::X { [0, 7] } else { [7, 0] };
+        let moves = if player == State::X { 0 } else { 1 };
+        Ur { slots, pots, moves, last_roll: None, played: Vec::new(), dice: UrDice::new(Dice::Binary(4)), rules: UrRules::default(), weights: UrWeights::default(), net: None }
+    }
+
+    #[test]
+    fn expectation_one_roll_from_the_end() {
+        use super::*;
+        use crate::score::ExpectedValue;
+        use crate::solver::expectiminimax;
+
+        // any roll but a zero bears X's last piece off the rosette.
+        let mut g = last_piece(State::X, 13);
+        let mut passed = g.clone();
+        passed.push(UrMove::Roll(0));
+        passed.push(UrMove::Pass);
+        let expected = 15.0 / 16.0 * MATE_SCORE as f64 + 1.0 / 16.0 * passed.heuristic() as f64;
+        assert_eq!(expectiminimax(&mut g, 1), ExpectedValue::new(expected));
+
+        // from square 12, O needs a two or more. a one lands on the rosette.
+        let mut g = last_piece(State::O, 12);
+        let mut passed = g.clone();
+        passed.push(UrMove::Roll(0));
+        passed.push(UrMove::Pass);
+        let rosette = last_piece(State::O, 13);
+        let expected = 11.0 / 16.0 * -MATE_SCORE as f64
+            + 4.0 / 16.0 * rosette.heuristic() as f64
+            + 1.0 / 16.0 * passed.heuristic() as f64;
+        assert_eq!(expectiminimax(&mut g, 1), ExpectedValue::new(expected));
+    }
+
+    #[test]
+    fn expectation_searches_agree_near_the_end() {
+        use super::*;
+        use std::collections::HashMap;
+        use crate::score::ExpectedValue;
+        use crate::solver::{expectiminimax, expectiminimax_ab, expectiminimax_tt, ChancePruning, SearchStats};
+
+        for g in [last_piece(State::X, 13), last_piece(State::X, 9), last_piece(State::O, 12)] {
+            for depth in 1..=3 {
+                let plain = expectiminimax(&mut g.clone(), depth);
+                let mut stats = SearchStats::default();
+                let star = expectiminimax_ab(&mut g.clone(), depth, -ExpectedValue::INFINITY, ExpectedValue::INFINITY, ChancePruning::Star2, &mut stats);
+                let cached = expectiminimax_tt(&mut g.clone(), depth, &mut HashMap::new(), &mut stats);
+                assert_eq!(plain, star);
+                assert_eq!(plain, cached);
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_dice() {
+        use super::*;
+        use rand::{rngs::StdRng, SeedableRng};
+        let game = Ur::new();
+        let rolls = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..50).map(|_| game.dice_roll(&mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(rolls(42), rolls(42));
+        assert!(rolls(42).iter().all(|&r| r <= 4));
+    }
+
+    #[test]
+    fn other_dice() {
+        use super::*;
+        use crate::score::ExpectedValue;
+        use crate::solver::expectiminimax;
+
+        let g = Ur::with_dice(Dice::Die(6));
+        let mut moves = Vec::new();
+        g.generate_moves(&mut moves);
+        assert_eq!(moves, (1..=6).map(UrMove::Roll).collect::<Vec<_>>());
+        // the rolls come with the dice's own probabilities.
+        let mut rolls = Vec::new();
+        g.generate_legal_moves_with_probabilities(&mut rolls);
+        let expected = Dice::Die(6).outcomes().into_iter().map(|(roll, prob)| (UrMove::Roll(roll), prob)).collect::<Vec<_>>();
+        assert_eq!(rolls, expected);
+
+        // every move in a position has its own index, whatever the dice.
+        for dice in [Dice::Binary(4), Dice::Binary(3), Dice::Die(6)] {
+            let mut g = Ur::with_dice(dice);
+            let mut rolls = Vec::new();
+            g.generate_moves(&mut rolls);
+            for &roll in &rolls {
+                g.push(roll);
+                let mut moves = Vec::new();
+                g.generate_moves(&mut moves);
+                g.pop(roll);
+                for moves in [&rolls, &moves] {
+                    let mut indices = moves.iter().map(|&m| g.move_index(m)).collect::<Vec<_>>();
+                    assert!(indices.iter().all(|&i| i < g.action_space_size()));
+                    indices.sort();
+                    indices.dedup();
+                    assert_eq!(indices.len(), moves.len());
+                }
+            }
+        }
+
+        // with loaded dice that always roll a one, X's last piece on 12
+        // always lands on the rosette rather than bearing off.
+        let mut g = last_piece(State::X, 12);
+        g.dice = UrDice::new(Dice::loaded(vec![0, 1]));
+        let rosette = last_piece(State::X, 13);
+        assert_eq!(expectiminimax(&mut g, 1), ExpectedValue::from_heuristic(rosette.heuristic()));
+    }
+
     #[test]
     fn rosette() {
         use super::*;
@@ -604,4 +1381,277 @@ Roll: ? | Pots: 6 X, 7 O
         }
         assert_eq!(g, before);
     }
-}
\ No newline at end of file
+
+    // X to move, with pieces on the given squares and none in the pots.
+    fn position(rules: super::UrRules, x: &[usize], o: &[usize]) -> super::Ur {
+        use super::*;
+        let mut game = Ur { pots: [0, 0], ..Ur::with_rules(rules) };
+        for &square in x {
+            game.slots.set(square, State::X);
+        }
+        for &square in o {
+            game.slots.set(square, State::O);
+        }
+        game
+    }
+
+    fn moves_after(mut game: super::Ur, roll: usize) -> Vec<super::UrMove> {
+        use super::*;
+        game.push(UrMove::Roll(roll));
+        let mut moves = Vec::new();
+        game.generate_moves(&mut moves);
+        moves
+    }
+
+    #[test]
+    fn tracks() {
+        use super::*;
+        for track in [Track::Bell, Track::Masters, Track::Aseb] {
+            // every square of a route is its own cell of the board.
+            let mut cells = (0..track.len()).map(|square| track.x_cell(square)).collect::<Vec<_>>();
+            cells.sort();
+            cells.dedup();
+            assert_eq!(cells.len(), track.len());
+            // the fourth square is a rosette on the player's own side.
+            assert!(track.is_rosette(track.cell(State::O, 3)));
+            assert!(cells.iter().all(|&(_, column)| column < track.columns()));
+        }
+        assert_eq!(Track::Masters.x_cell(11), (2, 6));
+        assert_eq!(Track::Masters.square_at(State::O, (2, 6)), Some(15));
+        assert_eq!(Track::Aseb.x_cell(15), (1, 11));
+        assert_eq!(Ur::with_rules(UrRules::blitz()).pots, [5, 5]);
+        assert_eq!(Ur::with_rules(UrRules::aseb()).to_string().lines().next().unwrap().len(), 23);
+    }
+
+    #[test]
+    fn exact_bear_off() {
+        use super::*;
+        // with overshoot, any roll that reaches the end bears off.
+        assert_eq!(moves_after(position(UrRules::default(), &[12], &[5]), 3), vec![UrMove::BearOff(12)]);
+        // with exact bear-off, only a roll of two will do.
+        assert_eq!(moves_after(position(UrRules::finkel(), &[12], &[5]), 3), vec![UrMove::Pass]);
+        assert_eq!(moves_after(position(UrRules::finkel(), &[12], &[5]), 2), vec![UrMove::BearOff(12)]);
+    }
+
+    #[test]
+    fn captures() {
+        use super::*;
+        // the central rosette is the eighth square of both routes.
+        let central = |rules| position(rules, &[5, 10], &[7]);
+        let unsafe_rosettes = UrRules { safe_rosettes: false, ..UrRules::default() };
+        assert!(moves_after(central(unsafe_rosettes), 2).contains(&UrMove::Move(Move { from: 5, to: 7, capture: true })));
+        for rules in [UrRules::default(), UrRules::finkel(), UrRules::aseb()] {
+            assert_eq!(moves_after(central(rules), 2), vec![UrMove::Move(Move { from: 10, to: 12, capture: false })]);
+        }
+
+        // squares on the players' own sides are never shared.
+        let own_sides = position(UrRules::default(), &[0], &[2]);
+        assert_eq!(moves_after(own_sides, 2), vec![UrMove::Move(Move { from: 0, to: 2, capture: false })]);
+
+        // on Masters' route, pieces pass through the opponent's side.
+        let mut masters = position(UrRules::masters(), &[10], &[15, 3]);
+        let capture = UrMove::Move(Move { from: 10, to: 11, capture: true });
+        assert_eq!(moves_after(masters.clone(), 1), vec![capture]);
+        let before = masters.clone();
+        masters.push(UrMove::Roll(1));
+        masters.push(capture);
+        assert_eq!(masters.pots, [0, 1]);
+        assert!(!masters.slots.test(15, State::O));
+        // the rosette gives X another turn.
+        assert_eq!(masters.turn(), 1);
+        masters.pop(capture);
+        masters.pop(UrMove::Roll(1));
+        assert_eq!(masters, before);
+    }
+
+    #[test]
+    fn capture_extra_turn() {
+        use super::*;
+        let capture = UrMove::Move(Move { from: 4, to: 6, capture: true });
+        for (rules, turn) in [(UrRules::masters(), -1), (UrRules::blitz(), 1)] {
+            let mut game = position(rules, &[4], &[6, 2]);
+            assert!(moves_after(game.clone(), 2).contains(&capture));
+            game.push(UrMove::Roll(2));
+            game.push(capture);
+            assert_eq!(game.turn(), turn);
+        }
+    }
+
+    #[test]
+    fn blocked_positions_pass() {
+        use super::*;
+        // one piece blocks the other, which can't land on the safe rosette.
+        assert_eq!(moves_after(position(UrRules::default(), &[3, 5], &[7]), 2), vec![UrMove::Pass]);
+        // a roll too long to bear off with.
+        assert_eq!(moves_after(position(UrRules::finkel(), &[13], &[7]), 3), vec![UrMove::Pass]);
+        // a roll of zero moves nothing.
+        assert_eq!(moves_after(position(UrRules::default(), &[3, 5], &[7]), 0), vec![UrMove::Pass]);
+        // a new piece can't enter onto one of our own.
+        let mut full = Ur::new();
+        full.slots.set(1, State::X);
+        assert_eq!(moves_after(full.clone(), 2), vec![UrMove::Move(Move { from: 1, to: 3, capture: false })]);
+        full.slots.set(3, State::X);
+        assert_eq!(moves_after(full.clone(), 2), vec![UrMove::Move(Move { from: 3, to: 5, capture: false })]);
+        // the pass is added even when the buffer already holds moves.
+        let mut blocked = position(UrRules::default(), &[3, 5], &[7]);
+        blocked.push(UrMove::Roll(2));
+        let mut moves = vec![UrMove::Roll(1)];
+        blocked.generate_moves(&mut moves);
+        assert_eq!(moves, vec![UrMove::Roll(1), UrMove::Pass]);
+        let before = blocked.clone();
+        blocked.push(UrMove::Pass);
+        assert_eq!(blocked.turn(), -1);
+        blocked.pop(UrMove::Pass);
+        assert_eq!(blocked, before);
+    }
+
+    #[test]
+    fn every_position_has_a_move() {
+        use super::*;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(42);
+        let rule_sets = [UrRules::default(), UrRules::finkel(), UrRules::masters(), UrRules::blitz(), UrRules::aseb()];
+        let mut moves = Vec::new();
+        for rules in rule_sets {
+            for _ in 0..200 {
+                let mut game = Ur::with_rules(rules);
+                let mut played = Vec::new();
+                while !game.is_terminal() {
+                    moves.clear();
+                    game.generate_moves(&mut moves);
+                    assert!(!moves.is_empty(), "no moves in\n{}", game);
+                    let m = moves[rng.gen_range(0..moves.len())];
+                    game.push(m);
+                    played.push(m);
+                    assert!(played.len() < 10_000, "game didn't end");
+                }
+                // and every move unmakes back to the start.
+                for &m in played.iter().rev() {
+                    game.pop(m);
+                }
+                assert_eq!(game, Ur::with_rules(rules));
+            }
+        }
+    }
+
+    #[test]
+    fn try_push_errors() {
+        use super::*;
+        let mut game = position(UrRules::finkel(), &[3, 5, 12], &[7]);
+        let before = game.clone();
+        let step = |from, to, capture| UrMove::Move(Move { from, to, capture });
+        assert_eq!(game.try_push(step(3, 5, false)), Err(UrMoveError::NotRolled));
+        assert_eq!(game.try_push(UrMove::Roll(5)), Err(UrMoveError::ImpossibleRoll(5)));
+        assert_eq!(game, before);
+        assert_eq!(game.try_push(UrMove::Roll(2)), Ok(()));
+        assert_eq!(game.try_push(UrMove::Roll(2)), Err(UrMoveError::AlreadyRolled));
+        assert_eq!(game.try_push(step(4, 6, false)), Err(UrMoveError::NoPiece(4)));
+        assert_eq!(game.try_push(step(14, 1, false)), Err(UrMoveError::NoPiece(14)));
+        assert_eq!(game.try_push(step(3, 6, false)), Err(UrMoveError::WrongDistance { roll: 2 }));
+        assert_eq!(game.try_push(step(3, 5, false)), Err(UrMoveError::Blocked(5)));
+        assert_eq!(game.try_push(step(5, 7, true)), Err(UrMoveError::Blocked(7)));
+        assert_eq!(game.try_push(UrMove::BearOff(5)), Err(UrMoveError::WrongDistance { roll: 2 }));
+        assert_eq!(game.try_push(UrMove::Pass), Err(UrMoveError::MustMove));
+        let rolled = game.clone();
+        // a wrong capture flag is refused, rather than corrupting the board.
+        let mut unsafe_rosettes = Ur { rules: UrRules::default(), ..game.clone() };
+        unsafe_rosettes.rules.safe_rosettes = false;
+        assert_eq!(unsafe_rosettes.try_push(step(5, 7, false)), Err(UrMoveError::WrongCapture));
+        assert_eq!(unsafe_rosettes.try_push(step(5, 7, true)), Ok(()));
+        assert_eq!(game, rolled);
+        assert_eq!(game.try_push(UrMove::BearOff(12)), Ok(()));
+        assert_eq!(game.to_string().lines().next(), Some("X . . .     . ."));
+        assert_eq!(UrMove::BearOff(12).to_string(), "12-off");
+    }
+
+    #[test]
+    fn legality_agrees_with_move_generation() {
+        use super::*;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(43);
+        let mut moves = Vec::new();
+        for rules in [UrRules::default(), UrRules::finkel(), UrRules::masters(), UrRules::blitz(), UrRules::aseb()] {
+            let end = rules.track.len();
+            // every move that could be written down, legal or not.
+            let mut candidates = vec![UrMove::Pass];
+            for from in 0..=end {
+                candidates.push(UrMove::BearOff(from));
+                candidates.push(UrMove::Roll(from));
+                for to in 0..=end {
+                    for capture in [false, true] {
+                        candidates.push(UrMove::Move(Move { from, to, capture }));
+                    }
+                }
+            }
+            for _ in 0..20 {
+                let mut game = Ur::with_rules(rules);
+                while !game.is_terminal() {
+                    moves.clear();
+                    game.generate_moves(&mut moves);
+                    for &m in &candidates {
+                        assert_eq!(game.is_legal(m), moves.contains(&m), "{} in\n{}", m, game);
+                    }
+                    game.try_push(moves[rng.gen_range(0..moves.len())]).unwrap();
+                }
+                assert_eq!(game.try_push(UrMove::Pass), Err(UrMoveError::GameOver));
+            }
+        }
+    }
+
+    #[test]
+    fn rendering() {
+        use super::*;
+        let mut game = Ur::new();
+        for m in ["roll 4", "14-3", "roll 2", "3-5", "roll 3"] {
+            let mut moves = Vec::new();
+            game.generate_moves(&mut moves);
+            game.push(*moves.iter().find(|m2| m2.to_string() == m).unwrap());
+        }
+        let expected = "\
+[*  ] .    .    .              *    .
+ . 4 [X 5] . 6  * 7  . 8  . 9  .10  .11
+ * 3  . 2  . 1  . 0            *13  .12
+
+X: 6 to enter, 0 borne off
+O: 7 to enter, 0 borne off
+last move: X 3-5
+O rolled 3, to move
+";
+        assert_eq!(game.rendering().to_string(), expected);
+        let coloured = game.rendering().with_colour(true).to_string();
+        assert!(coloured.contains("\x1b[7;1;33m[X 5]\x1b[0m"));
+        assert!(!game.rendering().to_string().contains('\x1b'));
+
+        // a bear-off, with the piece counted off the board.
+        let mut end = Ur::endgame(&[12], &[5], Player::X);
+        end.push(UrMove::Roll(3));
+        end.push(UrMove::BearOff(12));
+        let text = end.rendering().to_string();
+        assert!(text.starts_with(" *    .    .    .              *   [.  ]\n"), "{}", text);
+        assert!(text.contains("X: 0 to enter, 7 borne off\nO: 0 to enter, 6 borne off\nlast move: X 12-off\nO to roll\n"));
+    }
+
+    #[test]
+    fn features() {
+        use super::*;
+        // X holds the central rosette, and has a piece one square ahead of O's.
+        let game = position(UrRules::default(), &[7, 5], &[4]);
+        assert_eq!(game.features(), [8.0, 0.0, -1.0, 1.0, 0.25, 1.0]);
+        // without safe rosettes, the piece on the central rosette can be hit by a three.
+        let unsafe_rosettes = position(UrRules { safe_rosettes: false, ..UrRules::default() }, &[7, 5], &[4]);
+        assert_eq!(unsafe_rosettes.features()[4], 0.25 + 0.25);
+        // pieces are threatened from the board, and from the pot by a long enough roll.
+        let mut ahead = position(UrRules::default(), &[4, 2], &[0]);
+        ahead.pots = [0, 1];
+        assert_eq!(ahead.features()[1], -1.0);
+        assert_eq!(ahead.features()[4], 0.0625);
+        let d6 = Ur { dice: UrDice::new(Dice::Die(6)), ..ahead };
+        assert_eq!(d6.features()[4], 2.0 / 6.0);
+
+        let only_borne_off = UrWeights([0, 0, 1, 0, 0, 0]);
+        assert_eq!(game.clone().with_weights(only_borne_off).heuristic(), -1);
+        let reckless = UrWeights([i32::MAX; FEATURES.len()]);
+        assert_eq!(game.clone().with_weights(reckless).heuristic(), MATE_SCORE - 1);
+        assert_eq!(game.heuristic(), game.weights.evaluate(&game.features()) as i32);
+    }
+}
@@ -0,0 +1,267 @@
+use std::{fmt::{self, Display, Formatter}, ops::{Add, Neg, Sub}};
+
+// the value of a position from the perspective of the side to move.
+// scores are laid out on a single line so that they compare correctly:
+//
+//   -INFINITY < loss in 0 < loss in 1 < ... < heuristic values < ... < win in 1 < INFINITY
+//
+// wins and losses are counted in plies from the root of the search, so
+// that the search prefers quick wins and slow losses. a draw is zero,
+// which is also the value of a heuristic evaluation that is dead even.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Score(i32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreKind {
+    // the bounds of the search window, which no position is worth.
+    Infinity,
+    NegInfinity,
+    Win(usize),
+    Loss(usize),
+    Draw,
+    Heuristic(i32),
+}
+
+const MATE: i32 = 1_000_000_000;
+// mate scores are only meaningful up to this many plies from the root.
+const MAX_MATE_PLY: i32 = 100_000;
+const MAX_HEURISTIC: i32 = MATE - MAX_MATE_PLY - 1;
+
+impl Score {
+    pub const INFINITY: Self = Self(MATE + 1);
+    pub const DRAW: Self = Self(0);
+
+    pub fn win_in(plies: usize) -> Self {
+        Self(MATE - std::cmp::min(plies as i32, MAX_MATE_PLY))
+    }
+
+    pub fn loss_in(plies: usize) -> Self {
+        -Self::win_in(plies)
+    }
+
+    // heuristic values are clamped so they can never be mistaken for a win or a loss.
+    pub fn heuristic(value: i32) -> Self {
+        Self(value.clamp(-MAX_HEURISTIC, MAX_HEURISTIC))
+    }
+
+    pub fn kind(self) -> ScoreKind {
+        match self.0 {
+            0 => ScoreKind::Draw,
+            v if v == Self::INFINITY.0 => ScoreKind::Infinity,
+            v if v == -Self::INFINITY.0 => ScoreKind::NegInfinity,
+            v if v > MAX_HEURISTIC => ScoreKind::Win((MATE - v) as usize),
+            v if v < -MAX_HEURISTIC => ScoreKind::Loss((MATE + v) as usize),
+            v => ScoreKind::Heuristic(v),
+        }
+    }
+
+    pub fn is_win(self) -> bool {
+        matches!(self.kind(), ScoreKind::Win(_))
+    }
+
+    pub fn is_loss(self) -> bool {
+        matches!(self.kind(), ScoreKind::Loss(_))
+    }
+
+    pub fn is_mate(self) -> bool {
+        self.is_win() || self.is_loss()
+    }
+
+    pub fn raw(self) -> i32 {
+        self.0
+    }
+
+    // the inverse of raw(), for scores that have been packed away.
+    pub fn from_raw(raw: i32) -> Self {
+        Self(raw.clamp(-Self::INFINITY.0, Self::INFINITY.0))
+    }
+
+    // mate scores in the transposition table are stored relative to the
+    // position they belong to, rather than to the root, as the same position
+    // can be reached at different distances from the root. a mate closer to
+    // the root than the position itself, as a window bound can be, is stored
+    // as a mate at the position.
+    pub fn into_tt(self, ply: usize) -> Self {
+        match self.kind() {
+            ScoreKind::Win(n) => Self::win_in(n.saturating_sub(ply)),
+            ScoreKind::Loss(n) => Self::loss_in(n.saturating_sub(ply)),
+            _ => self,
+        }
+    }
+
+    pub fn out_of_tt(self, ply: usize) -> Self {
+        match self.kind() {
+            ScoreKind::Win(n) => Self::win_in(n + ply),
+            ScoreKind::Loss(n) => Self::loss_in(n + ply),
+            _ => self,
+        }
+    }
+}
+
+impl Neg for Score {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        // every score lies in -INFINITY..=INFINITY, so this can't overflow.
+        Self(-self.0)
+    }
+}
+
+// shifting a score saturates at +-INFINITY, so window arithmetic
+// like -alpha - 1 is safe at the edges of the range.
+impl Add<i32> for Score {
+    type Output = Self;
+
+    fn add(self, rhs: i32) -> Self::Output {
+        Self(self.0.saturating_add(rhs).clamp(-Self::INFINITY.0, Self::INFINITY.0))
+    }
+}
+
+impl Sub<i32> for Score {
+    type Output = Self;
+
+    fn sub(self, rhs: i32) -> Self::Output {
+        Self(self.0.saturating_sub(rhs).clamp(-Self::INFINITY.0, Self::INFINITY.0))
+    }
+}
+
+impl Display for Score {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.kind() {
+            ScoreKind::Infinity => write!(f, "+inf"),
+            ScoreKind::NegInfinity => write!(f, "-inf"),
+            ScoreKind::Win(n) => write!(f, "win in {}", n),
+            ScoreKind::Loss(n) => write!(f, "loss in {}", n),
+            ScoreKind::Draw => write!(f, "draw"),
+            ScoreKind::Heuristic(v) => write!(f, "{:+}", v),
+        }
+    }
+}
+
+// the value of a position in a game of chance: the heuristic value,
+// averaged over the rolls of the dice, from X's perspective.
+// probabilities and averages are kept as f64, which is exact for
+// dice whose probabilities are sixteenths, as in Ur.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct ExpectedValue(f64);
+
+impl ExpectedValue {
+    // larger than any value Game::heuristic can return.
+    pub const INFINITY: Self = Self(i32::MAX as f64);
+
+    pub fn new(value: f64) -> Self {
+        Self(value)
+    }
+
+    pub fn from_heuristic(value: i32) -> Self {
+        Self(value as f64)
+    }
+
+    // the expectation of the given values, each paired with its probability.
+    pub fn expectation(values: impl IntoIterator<Item = (Self, f64)>) -> Self {
+        Self(values.into_iter().fold(0.0, |sum, (value, prob)| sum + prob * value.0))
+    }
+
+    pub fn value(self) -> f64 {
+        self.0
+    }
+
+    pub fn max(self, other: Self) -> Self {
+        if other > self { other } else { self }
+    }
+
+    pub fn min(self, other: Self) -> Self {
+        if other < self { other } else { self }
+    }
+}
+
+impl Neg for ExpectedValue {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self(-self.0)
+    }
+}
+
+impl Display for ExpectedValue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:+.2}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExpectedValue, Score, ScoreKind};
+
+    #[test]
+    fn ordering() {
+        assert!(-Score::INFINITY < Score::loss_in(0));
+        assert!(Score::loss_in(0) < Score::loss_in(5));
+        assert!(Score::loss_in(5) < Score::heuristic(-1_000_000));
+        assert!(Score::heuristic(-1) < Score::DRAW);
+        assert!(Score::DRAW < Score::heuristic(1));
+        assert!(Score::heuristic(i32::MAX) < Score::win_in(5));
+        assert!(Score::win_in(5) < Score::win_in(1));
+        assert!(Score::win_in(1) < Score::INFINITY);
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(-Score::win_in(3), Score::loss_in(3));
+        assert_eq!(-Score::DRAW, Score::DRAW);
+        assert_eq!(-(-Score::INFINITY), Score::INFINITY);
+        assert_eq!(-Score::INFINITY - 1, -Score::INFINITY);
+        assert_eq!(Score::INFINITY + 1, Score::INFINITY);
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(Score::win_in(7).kind(), ScoreKind::Win(7));
+        assert_eq!(Score::loss_in(2).kind(), ScoreKind::Loss(2));
+        assert_eq!(Score::DRAW.kind(), ScoreKind::Draw);
+        assert_eq!(Score::heuristic(-250).kind(), ScoreKind::Heuristic(-250));
+        assert!(!Score::heuristic(i32::MIN).is_mate());
+        assert_eq!(Score::INFINITY.kind(), ScoreKind::Infinity);
+        assert_eq!((-Score::INFINITY).kind(), ScoreKind::NegInfinity);
+        assert!(!Score::INFINITY.is_mate());
+    }
+
+    #[test]
+    fn tt_roundtrip() {
+        let s = Score::win_in(9);
+        assert_eq!(s.into_tt(4), Score::win_in(5));
+        assert_eq!(s.into_tt(4).out_of_tt(4), s);
+        assert_eq!(Score::heuristic(40).into_tt(4), Score::heuristic(40));
+        // a bound from nearer the root than the position doesn't wrap.
+        assert_eq!(Score::win_in(2).into_tt(5), Score::win_in(0));
+        assert_eq!(Score::loss_in(3).into_tt(6), Score::loss_in(0));
+        // the window bounds are stored as they are, at any ply.
+        for s in [Score::INFINITY, -Score::INFINITY] {
+            assert_eq!(s.into_tt(4), s);
+            assert_eq!(s.out_of_tt(4), s);
+            assert_eq!(s.into_tt(0).out_of_tt(7), s);
+        }
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Score::win_in(5).to_string(), "win in 5");
+        assert_eq!(Score::loss_in(2).to_string(), "loss in 2");
+        assert_eq!(Score::DRAW.to_string(), "draw");
+        assert_eq!(Score::heuristic(-12).to_string(), "-12");
+        assert_eq!((-Score::INFINITY).to_string(), "-inf");
+    }
+
+    #[test]
+    fn expectation() {
+        // large values are weighted without overflow or rounding.
+        let mate = ExpectedValue::from_heuristic(1_000_000);
+        let value = ExpectedValue::expectation([(mate, 0.9375), (-mate, 0.0625)]);
+        assert_eq!(value, ExpectedValue::new(875_000.0));
+        let value = ExpectedValue::expectation([(ExpectedValue::from_heuristic(3), 0.25), (ExpectedValue::from_heuristic(1), 0.75)]);
+        assert_eq!(value.value(), 1.5);
+        assert_eq!(value.to_string(), "+1.50");
+        assert_eq!(value.max(mate), mate);
+        assert_eq!(value.min(-ExpectedValue::INFINITY), -ExpectedValue::INFINITY);
+    }
+}
@@ -1,8 +1,13 @@
-use std::collections::{HashMap, hash_map::Entry};
+use std::{collections::HashMap, fmt::{self, Display, Formatter}, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}};
 
-use crate::game::{Game, StochasticGame, PartiallySolvable, ToMove, Keyed};
+use rand::Rng;
 
-pub const INF: i32 = std::i32::MAX;
+use crate::{game::{Game, StochasticGame, PartiallySolvable, ToMove, Keyed}, ordering::{MoveOrdering, OrderingConfig}, score::{ExpectedValue, Score, ScoreKind}, winprob::Policy};
+
+pub const INF: Score = Score::INFINITY;
+
+// deep enough to reach the end of any of the games we solve.
+pub const MAX_DEPTH: usize = 1000;
 
 // fn solve(game: &mut impl Game) {
 //     let mut buffer = Vec::new();
@@ -15,24 +20,154 @@ pub const INF: i32 = std::i32::MAX;
 //     }).collect::<Vec<_>>();
 // }
 
-pub fn negamax<T>(node: &mut T, depth: usize, alpha: i32, beta: i32) -> i32
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    // the number of nodes visited, counting every call into the search.
+    pub nodes: u64,
+    // the number of times a search had to be repeated with a wider window,
+    // either a failed null-window probe in PVS or a failed aspiration window.
+    pub researches: u64,
+    // the number of zero-window searches made by MTD(f).
+    pub passes: u64,
+}
+
+// the state carried through a search: statistics, the move ordering
+// tables, the distance from the root of the node being searched, and
+// an optional flag that another thread can set to abandon the search.
+#[derive(Debug, Clone, Default)]
+pub struct SearchContext {
+    pub stats: SearchStats,
+    pub ordering: MoveOrdering,
+    pub stop: Option<Arc<AtomicBool>>,
+    ply: usize,
+}
+
+impl SearchContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_ordering(config: OrderingConfig) -> Self {
+        Self {
+            ordering: MoveOrdering::new(config),
+            ..Self::default()
+        }
+    }
+
+    // once this returns true, the values returned by the search are meaningless.
+    pub fn stopped(&self) -> bool {
+        self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+}
+
+// the score of a finished game for the side to move, where the game
+// ended ply plies from the root.
+// margins are scored by their sign, as we only care who wins.
+fn terminal_score<T: Game>(node: &T, ply: usize) -> Score {
+    let outcome = node.outcome().expect("terminal_score called on a game in progress");
+    match node.turn() * outcome.sign() {
+        1 => Score::win_in(ply),
+        -1 => Score::loss_in(ply),
+        _ => Score::DRAW,
+    }
+}
+
+// mate distance pruning: we can't do better than winning with our next
+// move, or worse than having already lost, so if a shorter mate has
+// been found elsewhere in the tree, there is nothing to find here.
+// returns the narrowed window, or the score to cut off with if it's empty.
+fn mate_distance_window(alpha: Score, beta: Score, ply: usize) -> Result<(Score, Score), Score> {
+    let alpha = std::cmp::max(alpha, Score::loss_in(ply));
+    let beta = std::cmp::min(beta, Score::win_in(ply + 1));
+    if alpha >= beta {
+        return Err(alpha);
+    }
+    Ok((alpha, beta))
+}
+
+pub fn negamax<T>(node: &mut T, depth: usize, alpha: Score, beta: Score, ctx: &mut SearchContext) -> Score
 where
     T: Game,
 {
-    if depth == 0 || node.is_terminal() {
-        return (node.turn() * node.evaluate()) as i32 * depth as i32;
+    ctx.stats.nodes += 1;
+    if node.is_terminal() {
+        return terminal_score(node, ctx.ply);
     }
+    if depth == 0 {
+        return Score::DRAW;
+    }
+
+    let (alpha, beta) = match mate_distance_window(alpha, beta, ctx.ply) {
+        Ok(window) => window,
+        Err(cutoff) => return cutoff,
+    };
 
     let mut buffer = Vec::with_capacity(node.action_space_size());
     node.generate_moves(&mut buffer);
+    ctx.ordering.order(node, &mut buffer, ctx.ply, None);
 
     let mut alpha = alpha;
     for m in buffer {
         node.push(m);
-        let value = -negamax(node, depth - 1, -beta, -alpha);
+        ctx.ply += 1;
+        let value = -negamax(node, depth - 1, -beta, -alpha, ctx);
+        ctx.ply -= 1;
+        node.pop(m);
+        alpha = std::cmp::max(alpha, value);
+        if alpha >= beta {
+            ctx.ordering.record_cutoff(node, m, ctx.ply, depth);
+            break;
+        }
+    }
+
+    alpha
+}
+
+// principal variation search: the first move is searched with the full window,
+// and every later move is first searched with a null window to prove that it is
+// no better than the current best. only moves that fail this proof are searched
+// again with the full window. returns the same values as negamax.
+pub fn pvs<T>(node: &mut T, depth: usize, alpha: Score, beta: Score, ctx: &mut SearchContext) -> Score
+where
+    T: Game,
+{
+    ctx.stats.nodes += 1;
+    if node.is_terminal() {
+        return terminal_score(node, ctx.ply);
+    }
+    if depth == 0 {
+        return Score::DRAW;
+    }
+
+    let (alpha, beta) = match mate_distance_window(alpha, beta, ctx.ply) {
+        Ok(window) => window,
+        Err(cutoff) => return cutoff,
+    };
+
+    let mut buffer = Vec::with_capacity(node.action_space_size());
+    node.generate_moves(&mut buffer);
+    ctx.ordering.order(node, &mut buffer, ctx.ply, None);
+
+    let mut alpha = alpha;
+    for (i, m) in buffer.into_iter().enumerate() {
+        node.push(m);
+        ctx.ply += 1;
+        let value = if i == 0 {
+            -pvs(node, depth - 1, -beta, -alpha, ctx)
+        } else {
+            let value = -pvs(node, depth - 1, -alpha - 1, -alpha, ctx);
+            if alpha < value && value < beta {
+                ctx.stats.researches += 1;
+                -pvs(node, depth - 1, -beta, -alpha, ctx)
+            } else {
+                value
+            }
+        };
+        ctx.ply -= 1;
         node.pop(m);
         alpha = std::cmp::max(alpha, value);
         if alpha >= beta {
+            ctx.ordering.record_cutoff(node, m, ctx.ply, depth);
             break;
         }
     }
@@ -40,73 +175,147 @@ where
     alpha
 }
 
+// mate scores are stored relative to the position, see Score::into_tt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TTScore {
-    Exact(i32),
-    LowerBound(i32),
-    UpperBound(i32),
+    Exact(Score),
+    LowerBound(Score),
+    UpperBound(Score),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TTEntry {
     pub depth: usize,
     pub value: TTScore,
+    // the Game::move_index of the best move found, if any move raised alpha.
+    pub best_move: Option<usize>,
+}
+
+pub trait TranspositionTable {
+    fn probe(&self, key: u64) -> Option<TTEntry>;
+    fn store(&mut self, key: u64, entry: TTEntry);
 }
 
-pub fn negamax_tt<T>(node: &mut T, depth: usize, alpha: i32, beta: i32, tt: &mut HashMap<u64, TTEntry>) -> i32
+impl TranspositionTable for HashMap<u64, TTEntry> {
+    fn probe(&self, key: u64) -> Option<TTEntry> {
+        self.get(&key).copied()
+    }
+
+    fn store(&mut self, key: u64, entry: TTEntry) {
+        self.insert(key, entry);
+    }
+}
+
+pub fn negamax_tt<T, TT>(node: &mut T, depth: usize, alpha: Score, beta: Score, tt: &mut TT, ctx: &mut SearchContext) -> Score
 where
     T: Game + Keyed,
+    TT: TranspositionTable,
 {
-    if depth == 0 || node.is_terminal() {
-        return (node.turn() * node.evaluate()) as i32 * depth as i32;
+    ctx.stats.nodes += 1;
+    if ctx.stopped() {
+        return Score::DRAW;
+    }
+    if node.is_terminal() {
+        return terminal_score(node, ctx.ply);
+    }
+    if depth == 0 {
+        return Score::DRAW;
     }
 
-    let (mut alpha, mut beta) = (alpha, beta);
+    let (alpha, beta) = match mate_distance_window(alpha, beta, ctx.ply) {
+        Ok(window) => window,
+        Err(cutoff) => return cutoff,
+    };
 
-    if let Some(entry) = tt.get(&node.hashkey()) {
+    let mut tt_move = None;
+
+    if let Some(entry) = tt.probe(node.hashkey()) {
+        tt_move = entry.best_move;
         if entry.depth >= depth {
             match entry.value {
-                TTScore::Exact(v) => return v,
-                TTScore::LowerBound(v) => alpha = std::cmp::max(v, alpha),
-                TTScore::UpperBound(v) => beta = std::cmp::min(v, beta),
-            }
-
-            if alpha >= beta {
-                return alpha;
+                TTScore::Exact(v) => return v.out_of_tt(ctx.ply),
+                TTScore::LowerBound(v) if v.out_of_tt(ctx.ply) >= beta => return v.out_of_tt(ctx.ply),
+                TTScore::UpperBound(v) if v.out_of_tt(ctx.ply) <= alpha => return v.out_of_tt(ctx.ply),
+                _ => {}
             }
         }
     }
 
     let mut buffer = Vec::with_capacity(node.action_space_size());
     node.generate_moves(&mut buffer);
+    ctx.ordering.order(node, &mut buffer, ctx.ply, tt_move);
 
-    let mut alpha = alpha;
+    // unlike negamax, this search is fail-soft: the value returned may lie
+    // outside the window, giving a tighter bound on the true value. MTD(f)
+    // relies on this to converge in a handful of passes.
     let inital_alpha = alpha;
+    let mut alpha = alpha;
+    let mut best_value = -INF;
+    let mut best_move = None;
     for m in buffer {
         node.push(m);
-        let value = -negamax(node, depth - 1, -beta, -alpha);
+        ctx.ply += 1;
+        let value = -negamax_tt(node, depth - 1, -beta, -alpha, tt, ctx);
+        ctx.ply -= 1;
         node.pop(m);
-        alpha = std::cmp::max(alpha, value);
+        if ctx.stopped() {
+            // the value is unreliable, so it mustn't reach the table.
+            return Score::DRAW;
+        }
+        best_value = std::cmp::max(best_value, value);
+        if value > alpha {
+            alpha = value;
+            best_move = Some(node.move_index(m));
+        }
         if alpha >= beta {
+            ctx.ordering.record_cutoff(node, m, ctx.ply, depth);
             break;
         }
     }
 
     let entry_to_save = TTEntry {
         depth,
-        value: if alpha <= inital_alpha {
-            TTScore::UpperBound(alpha)
-        } else if alpha >= beta {
-            TTScore::LowerBound(alpha)
+        value: if best_value <= inital_alpha {
+            TTScore::UpperBound(best_value.into_tt(ctx.ply))
+        } else if best_value >= beta {
+            TTScore::LowerBound(best_value.into_tt(ctx.ply))
         } else {
-            TTScore::Exact(alpha)
+            TTScore::Exact(best_value.into_tt(ctx.ply))
         },
+        best_move: best_move.or(tt_move),
     };
 
-    tt.insert(node.hashkey(), entry_to_save);
+    tt.store(node.hashkey(), entry_to_save);
 
-    alpha
+    best_value
 }
 
-pub fn expectiminimax<T>(node: &mut T, depth: usize) -> i32
+// MTD(f): converges on the minimax value with a series of zero-window
+// searches, each of which moves either the lower or the upper bound towards
+// the true value. the transposition table carries the work of each pass over
+// to the next, so a good first guess makes this very cheap.
+pub fn mtdf<T, TT>(node: &mut T, depth: usize, guess: Score, tt: &mut TT, ctx: &mut SearchContext) -> Score
+where
+    T: Game + Keyed,
+    TT: TranspositionTable,
+{
+    let mut value = guess;
+    let mut lower = -INF;
+    let mut upper = INF;
+    while lower < upper {
+        let beta = if value == lower { value + 1 } else { value };
+        value = negamax_tt(node, depth, beta - 1, beta, tt, ctx);
+        ctx.stats.passes += 1;
+        if value < beta {
+            upper = value;
+        } else {
+            lower = value;
+        }
+    }
+    value
+}
+
+pub fn expectiminimax<T>(node: &mut T, depth: usize) -> ExpectedValue
 where
     T: StochasticGame + PartiallySolvable,
 {
@@ -114,60 +323,283 @@ where
     // if node is a terminal node or depth = 0
     //    return the heuristic value of node
     if depth == 0 || node.is_terminal() {
-        return node.heuristic();
+        return ExpectedValue::from_heuristic(node.heuristic());
     }
 
     match node.to_move() {
         Max => {
             // Return value of maximum-valued child node
-            let mut max_value = -INF;
+            let mut max_value = -ExpectedValue::INFINITY;
             let mut buffer = Vec::with_capacity(node.action_space_size());
             node.generate_moves(&mut buffer);
             for m in buffer {
                 node.push(m);
                 let value = expectiminimax(node, depth - 1);
                 node.pop(m);
-                max_value = std::cmp::max(max_value, value);
+                max_value = max_value.max(value);
             }
             max_value
         },
         Min => {
             // Return value of minimum-valued child node
-            let mut min_value = INF;
+            let mut min_value = ExpectedValue::INFINITY;
             let mut buffer = Vec::with_capacity(node.action_space_size());
             node.generate_moves(&mut buffer);
             for m in buffer {
                 node.push(m);
                 let value = expectiminimax(node, depth - 1);
                 node.pop(m);
-                min_value = std::cmp::min(min_value, value);
+                min_value = min_value.min(value);
             }
             min_value
         },
         Chance => {
             // Return the average value of the child nodes,
             // weighted by the probability of the child nodes.
-            const SCALE_FACTOR: i32 = 1_000_000;
-            let mut scaled_value = 0;
             let mut buffer = Vec::with_capacity(node.action_space_size());
             node.generate_legal_moves_with_probabilities(&mut buffer);
-            for (m, prob) in buffer {
+            ExpectedValue::expectation(buffer.into_iter().map(|(m, prob)| {
                 node.push(m);
                 // don't reduce depth
                 let value = expectiminimax(node, depth);
                 node.pop(m);
-                scaled_value += ((value * SCALE_FACTOR) as f32 * prob) as i32;
+                (value, prob)
+            }))
+        },
+    }
+}
+
+// a cached chance node value. expectiminimax values are exact, so unlike
+// TTEntry there are no bounds to keep track of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChanceEntry {
+    pub depth: usize,
+    pub value: ExpectedValue,
+}
+
+// expectiminimax with a transposition table. chance nodes are the expensive
+// ones, as every roll has to be searched, so those are the only nodes cached.
+// entries are only used at exactly the depth they were searched to: a deeper
+// value would be no worse, but it would make the result depend on the order
+// of the search, where this returns exactly what expectiminimax does.
+pub fn expectiminimax_tt<T>(node: &mut T, depth: usize, tt: &mut HashMap<u64, ChanceEntry>, stats: &mut SearchStats) -> ExpectedValue
+where
+    T: StochasticGame + PartiallySolvable + Keyed,
+{
+    stats.nodes += 1;
+    if depth == 0 || node.is_terminal() {
+        return ExpectedValue::from_heuristic(node.heuristic());
+    }
+
+    match node.to_move() {
+        ToMove::Max | ToMove::Min => {
+            let maximising = node.to_move() == ToMove::Max;
+            let mut best = if maximising { -ExpectedValue::INFINITY } else { ExpectedValue::INFINITY };
+            let mut buffer = Vec::with_capacity(node.action_space_size());
+            node.generate_moves(&mut buffer);
+            for m in buffer {
+                node.push(m);
+                let value = expectiminimax_tt(node, depth - 1, tt, stats);
+                node.pop(m);
+                best = if maximising { best.max(value) } else { best.min(value) };
+            }
+            best
+        },
+        ToMove::Chance => {
+            let key = node.hashkey();
+            if let Some(entry) = tt.get(&key) {
+                if entry.depth == depth {
+                    return entry.value;
+                }
+            }
+            let mut buffer = Vec::with_capacity(node.action_space_size());
+            node.generate_legal_moves_with_probabilities(&mut buffer);
+            let value = ExpectedValue::expectation(buffer.into_iter().map(|(m, prob)| {
+                node.push(m);
+                let value = expectiminimax_tt(node, depth, tt, stats);
+                node.pop(m);
+                (value, prob)
+            }));
+            tt.insert(key, ChanceEntry { depth, value });
+            value
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChancePruning {
+    // bound the value of a chance node by the children searched so far,
+    // with the heuristic bounds standing in for the children still to come.
+    Star1,
+    // before searching any child fully, probe one move of each to get
+    // tighter bounds on all of them, then continue as in Star1.
+    Star2,
+}
+
+// expectiminimax with alpha-beta pruning that carries through chance nodes,
+// after Ballard's Star1 and Star2. values are always from X's perspective,
+// and fail hard: a value at or below alpha, or at or above beta, is only a
+// bound. with the full window it returns exactly what expectiminimax does.
+pub fn expectiminimax_ab<T>(node: &mut T, depth: usize, alpha: ExpectedValue, beta: ExpectedValue, pruning: ChancePruning, stats: &mut SearchStats) -> ExpectedValue
+where
+    T: StochasticGame + PartiallySolvable,
+{
+    stats.nodes += 1;
+    if depth == 0 || node.is_terminal() {
+        return ExpectedValue::from_heuristic(node.heuristic());
+    }
+
+    let (mut alpha, mut beta) = (alpha, beta);
+    match node.to_move() {
+        ToMove::Max => {
+            let mut buffer = Vec::with_capacity(node.action_space_size());
+            node.generate_moves(&mut buffer);
+            node.order_moves(&mut buffer);
+            for m in buffer {
+                node.push(m);
+                let value = expectiminimax_ab(node, depth - 1, alpha, beta, pruning, stats);
+                node.pop(m);
+                alpha = alpha.max(value);
+                if alpha >= beta {
+                    break;
+                }
             }
-            scaled_value / SCALE_FACTOR
+            alpha
         },
+        ToMove::Min => {
+            let mut buffer = Vec::with_capacity(node.action_space_size());
+            node.generate_moves(&mut buffer);
+            node.order_moves(&mut buffer);
+            for m in buffer {
+                node.push(m);
+                let value = expectiminimax_ab(node, depth - 1, alpha, beta, pruning, stats);
+                node.pop(m);
+                beta = beta.min(value);
+                if alpha >= beta {
+                    break;
+                }
+            }
+            beta
+        },
+        ToMove::Chance => star(node, depth, alpha, beta, pruning, stats),
+    }
+}
+
+fn star<T>(node: &mut T, depth: usize, alpha: ExpectedValue, beta: ExpectedValue, pruning: ChancePruning, stats: &mut SearchStats) -> ExpectedValue
+where
+    T: StochasticGame + PartiallySolvable,
+{
+    let mut buffer = Vec::with_capacity(node.action_space_size());
+    node.generate_legal_moves_with_probabilities(&mut buffer);
+    let probs = buffer.iter().map(|&(_, p)| p).collect::<Vec<_>>();
+    let weighted = |bounds: &[f64], from: usize| -> f64 {
+        probs[from..].iter().zip(&bounds[from..]).map(|(p, b)| p * b).sum()
+    };
+
+    // what we know about the value of each child, tightened as we go.
+    let (lower, upper) = node.heuristic_bounds();
+    let mut lo = vec![lower as f64; buffer.len()];
+    let mut hi = vec![upper as f64; buffer.len()];
+    let (alpha_f, beta_f) = (alpha.value(), beta.value());
+
+    // the window for child i in which its value could still matter, given
+    // that the children before it are worth `done` and the rest are unknown.
+    // the window is kept strictly wider than the child's known bounds, so
+    // that a child value on a bound isn't mistaken for a cutoff.
+    let window = |i: usize, done: f64, rest_lo: f64, rest_hi: f64, lo_i: f64, hi_i: f64| {
+        let a = (alpha_f - done - rest_hi) / probs[i];
+        let b = (beta_f - done - rest_lo) / probs[i];
+        (a.max(lo_i - 1.0), b.min(hi_i + 1.0))
+    };
+
+    if pruning == ChancePruning::Star2 {
+        for (i, &(m, _)) in buffer.iter().enumerate() {
+            let others_lo = weighted(&lo, 0) - probs[i] * lo[i];
+            let others_hi = weighted(&hi, 0) - probs[i] * hi[i];
+            let (a, b) = window(i, 0.0, others_lo, others_hi, lo[i], hi[i]);
+            node.push(m);
+            probe(node, depth, a, b, &mut lo[i], &mut hi[i], pruning, stats);
+            node.pop(m);
+            if weighted(&hi, 0) <= alpha_f {
+                return alpha;
+            }
+            if weighted(&lo, 0) >= beta_f {
+                return beta;
+            }
+        }
+    }
+
+    let mut done = 0.0;
+    for (i, &(m, _)) in buffer.iter().enumerate() {
+        let (rest_lo, rest_hi) = (weighted(&lo, i + 1), weighted(&hi, i + 1));
+        if done + probs[i] * hi[i] + rest_hi <= alpha_f {
+            return alpha;
+        }
+        if done + probs[i] * lo[i] + rest_lo >= beta_f {
+            return beta;
+        }
+        let (a, b) = window(i, done, rest_lo, rest_hi, lo[i], hi[i]);
+        node.push(m);
+        let value = expectiminimax_ab(node, depth, ExpectedValue::new(a), ExpectedValue::new(b), pruning, stats).value();
+        node.pop(m);
+        if value <= a {
+            return alpha;
+        }
+        if value >= b {
+            return beta;
+        }
+        // summed in the same order as ExpectedValue::expectation.
+        done += probs[i] * value;
+    }
+
+    ExpectedValue::new(done)
+}
+
+// the Star2 probe: search only the first move of a child of a chance node.
+// if the child is a Max node, that gives a lower bound on its value, and if
+// it is a Min node, an upper bound.
+#[allow(clippy::too_many_arguments)]
+fn probe<T>(node: &mut T, depth: usize, alpha: f64, beta: f64, lo: &mut f64, hi: &mut f64, pruning: ChancePruning, stats: &mut SearchStats)
+where
+    T: StochasticGame + PartiallySolvable,
+{
+    if node.is_terminal() {
+        let value = node.heuristic() as f64;
+        (*lo, *hi) = (value, value);
+        return;
+    }
+
+    let to_move = node.to_move();
+    if to_move == ToMove::Chance {
+        return;
+    }
+
+    let mut buffer = Vec::with_capacity(node.action_space_size());
+    node.generate_moves(&mut buffer);
+    node.order_moves(&mut buffer);
+    let Some(&m) = buffer.first() else {
+        return;
+    };
+    node.push(m);
+    let value = expectiminimax_ab(node, depth - 1, ExpectedValue::new(alpha), ExpectedValue::new(beta), pruning, stats).value();
+    node.pop(m);
+    match to_move {
+        ToMove::Max if value > alpha => *lo = lo.max(value),
+        ToMove::Min if value < beta => *hi = hi.min(value),
+        _ => (),
     }
 }
 
-pub fn solve(game: impl Game + Keyed) -> i32 {
+pub fn solve(game: impl Keyed) -> i32 {
     let mut game = game;
-    let value = negamax(&mut game, 1000, -INF, INF) * game.turn() as i32;
+    let value = negamax(&mut game, MAX_DEPTH, -INF, INF, &mut SearchContext::new());
     // 0 for a draw, N for mate-in-n for X, -N for mate-in-n for O
-    (1000 - value.abs()) * value.signum()
+    let solution = match value.kind() {
+        ScoreKind::Win(n) => n as i32,
+        ScoreKind::Loss(n) => -(n as i32),
+        _ => 0,
+    };
+    solution * game.turn() as i32
 }
 
 pub fn eval_to_string(solution: i32) -> String {
@@ -179,7 +611,7 @@ pub fn eval_to_string(solution: i32) -> String {
     }
 }
 
-pub fn print_solve_info(game: impl Game + Keyed) {
+pub fn print_solve_info(game: impl Keyed) {
     let start = std::time::Instant::now();
     let solution = solve(game);
     let time = start.elapsed().as_secs_f32();
@@ -196,33 +628,310 @@ pub fn best_move<G: Game>(game: G) -> G::Move {
         .iter()
         .max_by_key(|&&m| {
             game.push(m);
-            let value = -negamax(&mut game, 1000, -INF, INF);
+            let value = -negamax(&mut game, MAX_DEPTH, -INF, INF, &mut SearchContext::new());
             game.pop(m);
             value
         })
         .unwrap()
 }
 
-pub fn expecti_best_move<G>(game: G) -> G::Move
+// plays perfectly, by searching every move to the end of the game, or as
+// well as it can see within `depth` plies, with ties broken at random.
+// the positions searched are kept from one move to the next.
+#[derive(Debug, Clone, Default)]
+pub struct SolverPolicy {
+    pub depth: Option<usize>,
+    tt: HashMap<u64, TTEntry>,
+}
+
+impl SolverPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_depth(depth: usize) -> Self {
+        assert!(depth > 0);
+        Self {
+            depth: Some(depth),
+            ..Self::default()
+        }
+    }
+}
+
+impl<G: Keyed> Policy<G> for SolverPolicy {
+    fn choose<R: Rng>(&mut self, game: &G, moves: &[G::Move], rng: &mut R) -> G::Move {
+        let mut game = game.clone();
+        let depth = self.depth.unwrap_or(MAX_DEPTH) - 1;
+        let values = moves
+            .iter()
+            .map(|&m| {
+                game.push(m);
+                let value = -negamax_tt(&mut game, depth, -INF, INF, &mut self.tt, &mut SearchContext::new());
+                game.pop(m);
+                value
+            })
+            .collect::<Vec<_>>();
+        let best = *values.iter().max().unwrap();
+        let best = moves.iter().zip(&values).filter(|&(_, &v)| v == best).map(|(&m, _)| m).collect::<Vec<_>>();
+        best[rng.gen_range(0..best.len())]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Negamax,
+    Pvs,
+    Mtdf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchConfig {
+    pub algorithm: Algorithm,
+    pub depth: usize,
+    // the half-width of the window placed around the previous iteration's
+    // value, in raw score units, or None to search every iteration with the full window.
+    // MTD(f) always uses the previous value as its first guess instead.
+    pub aspiration_window: Option<i32>,
+    pub ordering: OrderingConfig,
+}
+
+impl SearchConfig {
+    pub fn new(algorithm: Algorithm, depth: usize) -> Self {
+        Self {
+            algorithm,
+            depth,
+            aspiration_window: None,
+            ordering: OrderingConfig::none(),
+        }
+    }
+
+    pub fn with_ordering(self, ordering: OrderingConfig) -> Self {
+        Self {
+            ordering,
+            ..self
+        }
+    }
+
+    pub fn with_aspiration_window(self, window: i32) -> Self {
+        Self {
+            aspiration_window: Some(window),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult {
+    pub value: Score,
+    pub depth: usize,
+    pub stats: SearchStats,
+}
+
+fn search_window<G: Keyed>(game: &mut G, algorithm: Algorithm, depth: usize, alpha: Score, beta: Score, ctx: &mut SearchContext) -> Score {
+    match algorithm {
+        Algorithm::Negamax => negamax(game, depth, alpha, beta, ctx),
+        Algorithm::Pvs => pvs(game, depth, alpha, beta, ctx),
+        Algorithm::Mtdf => unreachable!("MTD(f) does not search with a window"),
+    }
+}
+
+fn aspiration_search<G: Keyed>(game: &mut G, algorithm: Algorithm, depth: usize, guess: Score, window: i32, ctx: &mut SearchContext) -> Score {
+    let mut delta = window;
+    let mut alpha = guess - delta;
+    let mut beta = guess + delta;
+    loop {
+        let value = search_window(game, algorithm, depth, alpha, beta, ctx);
+        if value <= alpha && alpha > -INF {
+            delta = delta.saturating_mul(2);
+            alpha = guess - delta;
+        } else if value >= beta && beta < INF {
+            delta = delta.saturating_mul(2);
+            beta = guess + delta;
+        } else {
+            return value;
+        }
+        ctx.stats.researches += 1;
+    }
+}
+
+// searches to each depth from 1 to config.depth in turn, using the value of
+// each iteration to centre the aspiration window of the next, if enabled.
+// the value is from the perspective of the side to move, as in negamax.
+pub fn iterative_deepening<G: Keyed>(game: G, config: SearchConfig) -> SearchResult {
+    let mut game = game;
+    let mut ctx = SearchContext::with_ordering(config.ordering);
+    let mut tt = HashMap::new();
+    let mut value = Score::DRAW;
+    for depth in 1..=config.depth {
+        value = match (config.algorithm, config.aspiration_window, depth) {
+            (Algorithm::Mtdf, _, _) => mtdf(&mut game, depth, value, &mut tt, &mut ctx),
+            (_, Some(window), 2..) => aspiration_search(&mut game, config.algorithm, depth, value, window, &mut ctx),
+            _ => search_window(&mut game, config.algorithm, depth, -INF, INF, &mut ctx),
+        };
+    }
+    SearchResult {
+        value,
+        depth: config.depth,
+        stats: ctx.stats,
+    }
+}
+
+pub fn print_search_comparison(game: impl Keyed, depth: usize) {
+    let configs = [
+        ("negamax", SearchConfig::new(Algorithm::Negamax, depth)),
+        ("pvs", SearchConfig::new(Algorithm::Pvs, depth)),
+        ("pvs + aspiration", SearchConfig::new(Algorithm::Pvs, depth).with_aspiration_window(1)),
+        ("mtd(f)", SearchConfig::new(Algorithm::Mtdf, depth)),
+        ("negamax + order", SearchConfig::new(Algorithm::Negamax, depth).with_ordering(OrderingConfig::all())),
+        ("pvs + order", SearchConfig::new(Algorithm::Pvs, depth).with_ordering(OrderingConfig::all())),
+        ("mtd(f) + order", SearchConfig::new(Algorithm::Mtdf, depth).with_ordering(OrderingConfig::all())),
+    ];
+    for (name, config) in configs {
+        let start = std::time::Instant::now();
+        let result = iterative_deepening(game.clone(), config);
+        println!(
+            "{:>16}: value {:>9}, {:>10} nodes, {:>6} re-searches, {:>4} passes, done in {:.3}s",
+            name,
+            result.value.to_string(),
+            result.stats.nodes,
+            result.stats.researches,
+            result.stats.passes,
+            start.elapsed().as_secs_f32());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpectiConfig {
+    // the deepest iteration to search, in decision plies. at least one.
+    pub depth: usize,
+    // stop deepening once an iteration has taken the search past this many nodes.
+    pub nodes: Option<u64>,
+    // stop deepening once an iteration finishes after this much time.
+    pub time: Option<Duration>,
+    pub pruning: ChancePruning,
+}
+
+impl ExpectiConfig {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            nodes: None,
+            time: None,
+            pruning: ChancePruning::Star2,
+        }
+    }
+
+    pub fn with_nodes(self, nodes: u64) -> Self {
+        Self {
+            nodes: Some(nodes),
+            ..self
+        }
+    }
+
+    pub fn with_time(self, time: Duration) -> Self {
+        Self {
+            time: Some(time),
+            ..self
+        }
+    }
+
+    pub fn with_pruning(self, pruning: ChancePruning) -> Self {
+        Self {
+            pruning,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExpectiResult<M> {
+    pub best_move: M,
+    // the value of the best move, from X's perspective.
+    pub value: ExpectedValue,
+    // the depth of the last iteration that finished.
+    pub depth: usize,
+    pub stats: SearchStats,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectiError {
+    // the position is waiting on the dice, so there is no move to choose.
+    // chance moves should be sampled from the game's probabilities instead.
+    ChanceNode,
+    // the position has no moves to choose from.
+    NoMoves,
+}
+
+impl Display for ExpectiError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ExpectiError::ChanceNode => write!(f, "the position is a chance node"),
+            ExpectiError::NoMoves => write!(f, "the position has no moves"),
+        }
+    }
+}
+
+// picks the best move for the side to move: the highest expected value for X,
+// and the lowest for O. searches by iterative deepening until the depth limit,
+// or until the node or time limit runs out, and always finishes at least one
+// iteration. ties go to the move searched first.
+pub fn expecti_best_move<G>(game: G, config: ExpectiConfig) -> Result<ExpectiResult<G::Move>, ExpectiError>
 where
     G: StochasticGame + PartiallySolvable,
 {
+    let maximising = match game.to_move() {
+        ToMove::Max => true,
+        ToMove::Min => false,
+        ToMove::Chance => return Err(ExpectiError::ChanceNode),
+    };
     let mut game = game;
-    let mut moves = Vec::with_capacity(27);
-    game.generate_moves(&mut moves);
-    *moves
-        .iter()
-        .max_by_key(|&&m| {
+    let mut moves = Vec::with_capacity(game.action_space_size());
+    if !game.is_terminal() {
+        game.generate_moves(&mut moves);
+    }
+    if moves.is_empty() {
+        return Err(ExpectiError::NoMoves);
+    }
+    game.order_moves(&mut moves);
+
+    let start = Instant::now();
+    let mut stats = SearchStats::default();
+    let mut result = None;
+    for depth in 1..=std::cmp::max(config.depth, 1) {
+        let mut best: Option<(usize, ExpectedValue)> = None;
+        for (i, &m) in moves.iter().enumerate() {
+            // only a strictly better value can replace the best move,
+            // so the window only needs to show whether this one is.
+            let (alpha, beta) = match best {
+                None => (-ExpectedValue::INFINITY, ExpectedValue::INFINITY),
+                Some((_, value)) if maximising => (value, ExpectedValue::INFINITY),
+                Some((_, value)) => (-ExpectedValue::INFINITY, value),
+            };
             game.push(m);
-            let value = expectiminimax(&mut game, 5);
+            let value = expectiminimax_ab(&mut game, depth - 1, alpha, beta, config.pruning, &mut stats);
             game.pop(m);
-            match game.to_move() {
-                ToMove::Max => value,
-                ToMove::Min => -value,
-                ToMove::Chance => rand::random::<i32>().abs(),
+            let better = match best {
+                None => true,
+                Some((_, best_value)) if maximising => value > best_value,
+                Some((_, best_value)) => value < best_value,
+            };
+            if better {
+                best = Some((i, value));
             }
-        })
-        .unwrap()
+        }
+
+        let (i, value) = best.expect("there is at least one move");
+        result = Some(ExpectiResult { best_move: moves[i], value, depth, stats });
+        // search the best move first in the next iteration.
+        moves[..=i].rotate_right(1);
+
+        let out_of_nodes = config.nodes.is_some_and(|nodes| stats.nodes >= nodes);
+        let out_of_time = config.time.is_some_and(|time| start.elapsed() >= time);
+        if out_of_nodes || out_of_time {
+            break;
+        }
+    }
+    Ok(result.expect("at least one iteration is searched"))
 }
 
 pub fn principal_variation<G: Game>(game: G) -> Vec<G::Move> {
@@ -236,16 +945,17 @@ pub fn principal_variation<G: Game>(game: G) -> Vec<G::Move> {
     out
 }
 
-pub fn expecti_principal_variation<G>(game: G) -> Vec<G::Move>
+// the line of best play up to the next roll of the dice, after which
+// the best line depends on what is rolled.
+pub fn expecti_principal_variation<G>(game: G, config: ExpectiConfig) -> Vec<G::Move>
 where
     G: StochasticGame + PartiallySolvable,
 {
     let mut game = game;
     let mut out = Vec::new();
-    while !game.is_terminal() {
-        let m = expecti_best_move(game.clone());
-        game.push(m);
-        out.push(m);
+    while let Ok(result) = expecti_best_move(game.clone(), config) {
+        game.push(result.best_move);
+        out.push(result.best_move);
     }
     out
 }
@@ -254,12 +964,18 @@ where
 mod tests {
     use std::collections::HashMap;
 
+    use crate::adversarialknight::AdversarialKnight;
     use crate::coverttt::{CoverTTT, CoverTTTMove, Size};
-    use crate::game::Game;
+    use crate::driver::play_without_chance;
+    use crate::game::{Game, Outcome, Player};
 
     use crate::tictactoe::{TicTacToe, TicTacToeMove};
 
-    use super::{solve, negamax, INF, negamax_tt};
+    use super::{solve, negamax, INF, negamax_tt, pvs, SearchContext, iterative_deepening, SearchConfig, Algorithm, mtdf, expectiminimax, expectiminimax_ab, expectiminimax_tt, expecti_best_move, ChancePruning, ExpectiConfig, ExpectiError, SearchStats, SolverPolicy};
+    use crate::ordering::OrderingConfig;
+    use crate::rgu::{Ur, UrMove};
+    use crate::score::{ExpectedValue, Score};
+    use crate::winprob::RandomPolicy;
 
     #[test]
     fn ttt_root() {
@@ -309,11 +1025,250 @@ mod tests {
     #[test]
     fn hashtable_equivalence() {
         let game = TicTacToe::new();
-        let without_table = negamax(&mut game.clone(), 6, -INF, INF);
-        let with_table = negamax_tt(&mut game.clone(), 6, -INF, INF, &mut HashMap::new());
+        let without_table = negamax(&mut game.clone(), 6, -INF, INF, &mut SearchContext::new());
+        let with_table = negamax_tt(&mut game.clone(), 6, -INF, INF, &mut HashMap::new(), &mut SearchContext::new());
         assert_eq!(without_table, with_table);
-        let without2 = negamax(&mut game.clone(), 100, -INF, INF);
-        let with2 = negamax_tt(&mut game.clone(), 100, -INF, INF, &mut HashMap::new());
+        let without2 = negamax(&mut game.clone(), 100, -INF, INF, &mut SearchContext::new());
+        let with2 = negamax_tt(&mut game.clone(), 100, -INF, INF, &mut HashMap::new(), &mut SearchContext::new());
         assert_eq!(without2, with2);
     }
+
+    #[test]
+    fn knight_hashtable_equivalence() {
+        // the table and the move ordering index moves after taking them
+        // back, which needs pop to put the knight back where it was.
+        let game = AdversarialKnight::new();
+        for depth in [1, 4, 8] {
+            let without_table = negamax(&mut game.clone(), depth, -INF, INF, &mut SearchContext::new());
+            let mut ctx = SearchContext::with_ordering(OrderingConfig::all());
+            let mut searched = game.clone();
+            let with_table = negamax_tt(&mut searched, depth, -INF, INF, &mut HashMap::new(), &mut ctx);
+            assert_eq!(without_table, with_table);
+            assert_eq!(searched, game);
+        }
+    }
+
+    fn cttt_xwin_root() -> CoverTTT {
+        let mut root = CoverTTT::new();
+        root.push(CoverTTTMove::new(4, Size::Big));
+        root.push(CoverTTTMove::new(1, Size::Small));
+        root
+    }
+
+    #[test]
+    fn pvs_equivalence() {
+        for depth in 1..=9 {
+            let game = TicTacToe::new();
+            let plain = negamax(&mut game.clone(), depth, -INF, INF, &mut SearchContext::new());
+            let scout = pvs(&mut game.clone(), depth, -INF, INF, &mut SearchContext::new());
+            assert_eq!(plain, scout);
+        }
+        for depth in 1..=7 {
+            let game = cttt_xwin_root();
+            let plain = negamax(&mut game.clone(), depth, -INF, INF, &mut SearchContext::new());
+            let scout = pvs(&mut game.clone(), depth, -INF, INF, &mut SearchContext::new());
+            assert_eq!(plain, scout);
+        }
+    }
+
+    #[test]
+    fn aspiration_equivalence() {
+        let ttt = TicTacToe::new();
+        let cttt = cttt_xwin_root();
+        for depth in [5, 9] {
+            let plain = negamax(&mut ttt.clone(), depth, -INF, INF, &mut SearchContext::new());
+            for window in [1, 3] {
+                let config = SearchConfig::new(Algorithm::Pvs, depth).with_aspiration_window(window);
+                assert_eq!(iterative_deepening(ttt, config).value, plain);
+            }
+        }
+        for depth in [3, 6] {
+            let plain = negamax(&mut cttt.clone(), depth, -INF, INF, &mut SearchContext::new());
+            for window in [1, 3] {
+                let config = SearchConfig::new(Algorithm::Pvs, depth).with_aspiration_window(window);
+                assert_eq!(iterative_deepening(cttt, config).value, plain);
+            }
+        }
+    }
+
+    #[test]
+    fn mtdf_equivalence() {
+        for depth in 1..=9 {
+            let game = TicTacToe::new();
+            let plain = negamax(&mut game.clone(), depth, -INF, INF, &mut SearchContext::new());
+            let mut ctx = SearchContext::new();
+            let value = mtdf(&mut game.clone(), depth, Score::DRAW, &mut HashMap::new(), &mut ctx);
+            assert_eq!(plain, value);
+            assert!(ctx.stats.passes > 0);
+        }
+        let cttt = cttt_xwin_root();
+        for depth in [3, 6, 7] {
+            let plain = negamax(&mut cttt.clone(), depth, -INF, INF, &mut SearchContext::new());
+            let result = iterative_deepening(cttt, SearchConfig::new(Algorithm::Mtdf, depth));
+            assert_eq!(plain, result.value);
+        }
+    }
+
+    #[test]
+    fn mate_distance() {
+        let mut root = TicTacToe::new();
+        root.push(TicTacToeMove::new(4));
+        root.push(TicTacToeMove::new(1));
+
+        // the side to move wins in five plies, however deep we look.
+        for depth in 5..=9 {
+            assert_eq!(negamax(&mut root.clone(), depth, -INF, INF, &mut SearchContext::new()), Score::win_in(5));
+            assert_eq!(negamax_tt(&mut root.clone(), depth, -INF, INF, &mut HashMap::new(), &mut SearchContext::new()), Score::win_in(5));
+            let config = SearchConfig::new(Algorithm::Mtdf, depth).with_ordering(OrderingConfig::all());
+            assert_eq!(iterative_deepening(root, config).value, Score::win_in(5));
+        }
+        // but not if we can't see the end.
+        assert_eq!(negamax(&mut root.clone(), 4, -INF, INF, &mut SearchContext::new()), Score::DRAW);
+    }
+
+    #[test]
+    fn node_counts() {
+        let mut ctx = SearchContext::new();
+        negamax(&mut TicTacToe::new(), 1, -INF, INF, &mut ctx);
+        // the root and its nine children
+        assert_eq!(ctx.stats.nodes, 10);
+        assert_eq!(ctx.stats.researches, 0);
+    }
+
+    #[test]
+    fn ordering_equivalence() {
+        let ttt = TicTacToe::new();
+        let cttt = cttt_xwin_root();
+        for algorithm in [Algorithm::Negamax, Algorithm::Pvs, Algorithm::Mtdf] {
+            for depth in [4, 9] {
+                let plain = iterative_deepening(ttt, SearchConfig::new(algorithm, depth));
+                let ordered = iterative_deepening(ttt, SearchConfig::new(algorithm, depth).with_ordering(OrderingConfig::all()));
+                assert_eq!(plain.value, ordered.value);
+                // good ordering should make a full tic-tac-toe search cheaper.
+                assert!(depth < 9 || ordered.stats.nodes < plain.stats.nodes);
+            }
+            let plain = iterative_deepening(cttt, SearchConfig::new(algorithm, 7));
+            let ordered = iterative_deepening(cttt, SearchConfig::new(algorithm, 7).with_ordering(OrderingConfig::all()));
+            assert_eq!(plain.value, ordered.value);
+        }
+    }
+
+    // a few Ur positions, reached by following the given rolls
+    // and always playing the first legal move.
+    fn ur_positions() -> Vec<Ur> {
+        let mut out = vec![Ur::new()];
+        for rolls in [[2, 3, 1, 4], [4, 4, 2, 0], [1, 2, 3, 2]] {
+            let mut game = Ur::new();
+            for roll in rolls {
+                game.push(UrMove::Roll(roll));
+                let mut moves = Vec::new();
+                game.generate_moves(&mut moves);
+                game.push(moves[0]);
+            }
+            out.push(game);
+        }
+        out
+    }
+
+    #[test]
+    fn star_equivalence() {
+        for game in ur_positions() {
+            for depth in 1..=3 {
+                let plain = expectiminimax(&mut game.clone(), depth);
+                for pruning in [ChancePruning::Star1, ChancePruning::Star2] {
+                    let mut stats = SearchStats::default();
+                    let mut pruned_game = game.clone();
+                    let pruned = expectiminimax_ab(&mut pruned_game, depth, -ExpectedValue::INFINITY, ExpectedValue::INFINITY, pruning, &mut stats);
+                    assert_eq!(plain, pruned, "{:?} disagrees at depth {}", pruning, depth);
+                    assert_eq!(pruned_game, game);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn expectiminimax_tt_equivalence() {
+        for game in ur_positions() {
+            for depth in 1..=3 {
+                let plain = expectiminimax(&mut game.clone(), depth);
+                let mut stats = SearchStats::default();
+                let mut cached_game = game.clone();
+                let cached = expectiminimax_tt(&mut cached_game, depth, &mut HashMap::new(), &mut stats);
+                assert_eq!(plain, cached, "disagrees at depth {}", depth);
+                assert_eq!(cached_game, game);
+            }
+        }
+    }
+
+    #[test]
+    fn expectiminimax_tt_reuses_entries() {
+        let mut game = Ur::new();
+        let mut tt = HashMap::new();
+        let mut first = SearchStats::default();
+        let value = expectiminimax_tt(&mut game, 3, &mut tt, &mut first);
+        // the root is a chance node, so a second search finds it in the table.
+        let mut second = SearchStats::default();
+        assert_eq!(expectiminimax_tt(&mut game, 3, &mut tt, &mut second), value);
+        assert_eq!(second.nodes, 1);
+        assert!(first.nodes > 1);
+    }
+
+    #[test]
+    fn expecti_best_move_by_side() {
+        for game in ur_positions() {
+            for roll in 1..=4 {
+                let mut game = game.clone();
+                game.push(UrMove::Roll(roll));
+                let mut moves = Vec::new();
+                game.generate_moves(&mut moves);
+                let values = moves
+                    .iter()
+                    .map(|&m| {
+                        game.push(m);
+                        let value = expectiminimax(&mut game, 1);
+                        game.pop(m);
+                        value
+                    })
+                    .collect::<Vec<_>>();
+                // X takes the highest value and O the lowest.
+                let best = values
+                    .iter()
+                    .copied()
+                    .reduce(|a, b| if game.turn() == 1 { a.max(b) } else { a.min(b) })
+                    .unwrap();
+                let result = expecti_best_move(game.clone(), ExpectiConfig::new(2)).unwrap();
+                assert_eq!(result.value, best);
+                assert_eq!(result.depth, 2);
+                let i = moves.iter().position(|&m| m == result.best_move).unwrap();
+                assert_eq!(values[i], best);
+            }
+        }
+    }
+
+    #[test]
+    fn expecti_best_move_refuses_chance_nodes() {
+        let game = Ur::new();
+        assert_eq!(expecti_best_move(game, ExpectiConfig::new(2)), Err(ExpectiError::ChanceNode));
+    }
+
+    #[test]
+    fn expecti_best_move_limits() {
+        let mut game = Ur::new();
+        game.push(UrMove::Roll(2));
+        // a node budget that the first iteration exhausts stops the search there.
+        let result = expecti_best_move(game.clone(), ExpectiConfig::new(5).with_nodes(1)).unwrap();
+        assert_eq!(result.depth, 1);
+        let result = expecti_best_move(game, ExpectiConfig::new(3).with_pruning(ChancePruning::Star1)).unwrap();
+        assert_eq!(result.depth, 3);
+    }
+
+    #[test]
+    fn solver_never_loses() {
+        for seed in 0..4 {
+            let (end, _) = play_without_chance(TicTacToe::new(), &mut SolverPolicy::new(), &mut SolverPolicy::new(), seed);
+            assert_eq!(end.outcome(), Some(Outcome::Draw));
+            let (end, _) = play_without_chance(TicTacToe::new(), &mut RandomPolicy, &mut SolverPolicy::with_depth(9), seed);
+            assert_ne!(end.outcome().and_then(Outcome::winner), Some(Player::X));
+        }
+    }
 }
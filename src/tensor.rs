@@ -0,0 +1,122 @@
+use crate::game::Game;
+
+// games as fixed-size numeric input for machine learning. a position is
+// encoded as a flat vector of f32 with a shape, such as planes × rows ×
+// columns, that is the same for every position of a game, and moves are
+// mapped to and from a dense action index in 0..action_space_size().
+pub trait TensorEncode: Game {
+    // the dimensions of the encoding, outermost first.
+    fn tensor_shape(&self) -> Vec<usize>;
+
+    // the position from the point of view of the side to move, so that a
+    // model sees its own pieces in the same place whichever side it plays.
+    // the values are laid out in row-major order over tensor_shape().
+    fn encode_tensor(&self) -> Vec<f32>;
+
+    fn action_index(&self, m: Self::Move) -> usize {
+        self.move_index(m)
+    }
+
+    // the legal move with the given action index, if there is one.
+    fn action_to_move(&self, index: usize) -> Option<Self::Move> {
+        let mut moves = Vec::with_capacity(self.action_space_size());
+        self.generate_moves(&mut moves);
+        moves.into_iter().find(|&m| self.action_index(m) == index)
+    }
+
+    // for each action index, whether it is a legal move.
+    fn action_mask(&self) -> Vec<bool> {
+        let mut mask = vec![false; self.action_space_size()];
+        let mut moves = Vec::with_capacity(self.action_space_size());
+        self.generate_moves(&mut moves);
+        for m in moves {
+            mask[self.action_index(m)] = true;
+        }
+        mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::adversarialknight::AdversarialKnight;
+    use crate::connect4::Connect4;
+    use crate::coverttt::CoverTTT;
+    use crate::game::{Game, Player};
+    use crate::rgu::Ur;
+    use crate::tictactoe::{TicTacToe, TicTacToeMove};
+
+    use super::TensorEncode;
+
+    // plays random games, checking at every position that each legal move
+    // survives the trip to an action index and back, that no other index
+    // gives a move, and that the encoding always has the same shape.
+    fn check_round_trip<G: TensorEncode>(game: G, games: usize)
+    where
+        G::Move: PartialEq + std::fmt::Debug,
+    {
+        let mut rng = StdRng::seed_from_u64(9);
+        let shape = game.tensor_shape();
+        for _ in 0..games {
+            let mut game = game.clone();
+            loop {
+                assert_eq!(game.tensor_shape(), shape);
+                assert_eq!(game.encode_tensor().len(), shape.iter().product::<usize>());
+                if game.is_terminal() {
+                    break;
+                }
+                let mut moves = Vec::new();
+                game.generate_moves(&mut moves);
+                let mask = game.action_mask();
+                assert_eq!(mask.len(), game.action_space_size());
+                assert_eq!(mask.iter().filter(|&&legal| legal).count(), moves.len(), "indices collide in\n{}", game);
+                for &m in &moves {
+                    let index = game.action_index(m);
+                    assert!(index < game.action_space_size());
+                    assert_eq!(game.action_to_move(index), Some(m), "{} in\n{}", m, game);
+                }
+                for index in (0..game.action_space_size()).filter(|&i| !mask[i]) {
+                    assert_eq!(game.action_to_move(index), None);
+                }
+                game.push(moves[rng.gen_range(0..moves.len())]);
+            }
+        }
+    }
+
+    #[test]
+    fn action_round_trips() {
+        check_round_trip(TicTacToe::new(), 50);
+        check_round_trip(CoverTTT::new(), 50);
+        check_round_trip(Connect4::new(), 50);
+        check_round_trip(AdversarialKnight::new(), 20);
+        check_round_trip(Ur::new(), 20);
+    }
+
+    #[test]
+    fn relative_to_side_to_move() {
+        let mut game = TicTacToe::new();
+        assert_eq!(game.tensor_shape(), vec![2, 3, 3]);
+        assert!(game.encode_tensor().iter().all(|&x| x == 0.0));
+        game.push(TicTacToeMove::new(4));
+        // O to move sees X's stone among the opponent's.
+        let encoded = game.encode_tensor();
+        assert_eq!(encoded[4], 0.0);
+        assert_eq!(encoded[9 + 4], 1.0);
+
+        let mut connect4 = Connect4::new();
+        connect4.push(3);
+        connect4.push(3);
+        let encoded = connect4.encode_tensor();
+        // X to move again, with its stone at the bottom of the middle column.
+        assert_eq!(encoded[3], 1.0);
+        assert_eq!(encoded[42 + 7 + 3], 1.0);
+        assert_eq!(encoded.iter().sum::<f32>(), 2.0);
+
+        // the same race, with the sides swapped, looks the same to the side to move.
+        let x_ahead = Ur::endgame(&[9, 2], &[5], Player::X);
+        let o_ahead = Ur::endgame(&[5], &[9, 2], Player::O);
+        assert_eq!(x_ahead.encode_tensor(), o_ahead.encode_tensor());
+        assert_ne!(x_ahead.encode_tensor(), Ur::endgame(&[9, 2], &[5], Player::O).encode_tensor());
+    }
+}
@@ -0,0 +1,40 @@
+use std::{
+    fmt::{Debug, Display},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::io::{self, ParseError};
+
+// checks shared by the tests of the learned models.
+
+// checks a gradient against finite differences of f, nudging each
+// parameter in turn.
+pub fn assert_gradient_matches(params: &[f64], gradient: &[f64], f: impl Fn(&[f64]) -> f64, tolerance: f64) {
+    const STEP: f64 = 1e-6;
+    assert_eq!(gradient.len(), params.len());
+    let at = f(params);
+    let mut nudged = params.to_vec();
+    for (i, g) in gradient.iter().enumerate() {
+        nudged[i] += STEP;
+        let estimate = (f(&nudged) - at) / STEP;
+        nudged[i] = params[i];
+        assert!((estimate - g).abs() < tolerance, "parameter {}: {} against {}", i, g, estimate);
+    }
+}
+
+// checks that a value written out by Display reads back the same, from
+// the text and from a file.
+pub fn assert_round_trip<T, P>(value: &T, parse: P)
+where
+    T: Display + PartialEq + Debug,
+    P: Fn(&str) -> Result<T, ParseError>,
+{
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+    assert_eq!(&parse(&value.to_string()).unwrap(), value);
+    let file = format!("round-trip-{}-{}.txt", std::process::id(), FILES.fetch_add(1, Ordering::Relaxed));
+    let path = std::env::temp_dir().join(file);
+    std::fs::write(&path, value.to_string()).unwrap();
+    let loaded = io::load(&path, parse);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(&loaded.unwrap(), value);
+}
@@ -2,7 +2,7 @@
 
 use std::fmt::Display;
 
-use crate::game::{Game, ToMove, Keyed};
+use crate::{game::{Game, ToMove, Keyed, Outcome, Player}, tensor::TensorEncode};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TicTacToe {
@@ -48,6 +48,41 @@ impl TicTacToe {
         self.board[0] & (1 << i) != 0
     }
 
+    fn last_mover(&self) -> Player {
+        if self.moves & 1 == 0 {
+            Player::O
+        } else {
+            Player::X
+        }
+    }
+
+    fn has_line(&self) -> bool {
+        // check first diagonal
+        if self.probe_spot(0) && self.probe_spot(4) && self.probe_spot(8) {
+            return true;
+        }
+
+        // check second diagonal
+        if self.probe_spot(2) && self.probe_spot(4) && self.probe_spot(6) {
+            return true;
+        }
+
+        // check rows
+        for i in 0..3 {
+            if self.probe_spot(i * 3) && self.probe_spot(i * 3 + 1) && self.probe_spot(i * 3 + 2) {
+                return true;
+            }
+        }
+        // check columns
+        for i in 0..3 {
+            if self.probe_spot(i) && self.probe_spot(i + 3) && self.probe_spot(i + 6) {
+                return true;
+            }
+        }
+
+        false
+    }
+
     fn char_at(&self, x: usize, y: usize) -> char {
         if self.pos_filled(x * 3 + y) {
             if self.player_at(x * 3 + y) {
@@ -80,35 +115,15 @@ impl Game for TicTacToe {
         }
     }
 
-    fn evaluate(&self) -> i8 {
-        // check first diagonal
-        if self.probe_spot(0) && self.probe_spot(4) && self.probe_spot(8) {
-            return -self.turn();
-        }
-
-        // check second diagonal
-        if self.probe_spot(2) && self.probe_spot(4) && self.probe_spot(6) {
-            return -self.turn();
-        }
-
-        // check rows
-        for i in 0..3 {
-            if self.probe_spot(i * 3) && self.probe_spot(i * 3 + 1) && self.probe_spot(i * 3 + 2) {
-                return -self.turn();
-            }
-        }
-        // check columns
-        for i in 0..3 {
-            if self.probe_spot(i) && self.probe_spot(i + 3) && self.probe_spot(i + 6) {
-                return -self.turn();
-            }
+    fn outcome(&self) -> Option<Outcome> {
+        if self.has_line() {
+            // only the player who just moved can have made a line.
+            Some(Outcome::Win(self.last_mover()))
+        } else if self.moves == 9 {
+            Some(Outcome::Draw)
+        } else {
+            None
         }
-
-        0
-    }
-
-    fn is_terminal(&self) -> bool {
-        self.moves == 9 || self.evaluate() != 0
     }
 
     fn generate_moves(&self, buffer: &mut Vec<Self::Move>) {
@@ -133,6 +148,16 @@ impl Game for TicTacToe {
     fn action_space_size(&self) -> usize {
         9
     }
+
+    fn move_index(&self, m: Self::Move) -> usize {
+        m.0
+    }
+
+    fn order_moves(&self, buffer: &mut [Self::Move]) {
+        // the centre is on four lines, the corners on three and the edges on two.
+        const LINES_THROUGH: [u8; 9] = [3, 2, 3, 2, 4, 2, 3, 2, 3];
+        buffer.sort_by_key(|m| std::cmp::Reverse(LINES_THROUGH[m.0]));
+    }
 }
 
 impl Keyed for TicTacToe {
@@ -141,6 +166,25 @@ impl Keyed for TicTacToe {
     }
 }
 
+impl TensorEncode for TicTacToe {
+    fn tensor_shape(&self) -> Vec<usize> {
+        vec![2, 3, 3]
+    }
+
+    fn encode_tensor(&self) -> Vec<f32> {
+        // a plane for the side to move's marks, then one for the opponent's.
+        let planes = [self.board[self.moves & 1], self.board[(self.moves + 1) & 1]];
+        planes
+            .iter()
+            .flat_map(|&plane| (0..9).map(move |i| (plane >> i & 1) as f32))
+            .collect()
+    }
+
+    fn action_to_move(&self, index: usize) -> Option<Self::Move> {
+        (index < 9 && !self.pos_filled(index)).then_some(TicTacToeMove(index))
+    }
+}
+
 impl Display for TicTacToe {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for y in 0..3 {
@@ -156,9 +200,10 @@ impl Display for TicTacToe {
 
 #[cfg(test)]
 mod tests {
-    use crate::perft::perft;
+    use crate::game::{Game, Outcome, Player};
+    use crate::perft::{perft, perft_outcomes, OutcomeCounts};
 
-    use super::TicTacToe;
+    use super::{TicTacToe, TicTacToeMove};
 
     #[test]
     fn depth1() {
@@ -177,4 +222,26 @@ mod tests {
         let mut board = TicTacToe::new();
         assert_eq!(perft(&mut board, 10), 255168);
     }
+
+    #[test]
+    fn outcomes() {
+        let mut board = TicTacToe::new();
+        assert_eq!(board.outcome(), None);
+        for m in [0, 3, 1, 4, 2] {
+            board.push(TicTacToeMove::new(m));
+        }
+        assert_eq!(board.outcome(), Some(Outcome::Win(Player::X)));
+    }
+
+    #[test]
+    fn full_game_outcomes() {
+        let mut board = TicTacToe::new();
+        let counts = perft_outcomes(&mut board, 9);
+        assert_eq!(counts, OutcomeCounts {
+            x_wins: 131184,
+            o_wins: 77904,
+            draws: 46080,
+            unfinished: 0,
+        });
+    }
 }
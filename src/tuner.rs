@@ -0,0 +1,472 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    driver,
+    game::{StochasticGame, ToMove, Tunable},
+    record::GameRecord,
+    winprob::Policy,
+};
+
+// learns the weights of a game's heuristic from self-play. the heuristic,
+// divided by a scale, is read as the log odds of X winning, so that tuning
+// is a fit of a logistic model of the result on the features.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    // logistic regression of each position's result on its features, as in
+    // the Texel method, fitted by Newton's method.
+    Texel { iterations: usize },
+    // TD(λ): each position's prediction is moved towards the next one's,
+    // and the last towards the result.
+    TemporalDifference { lambda: f64, alpha: f64, epochs: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TunerConfig {
+    // the number of self-play games to learn from.
+    pub games: usize,
+    // the fraction of the games held back to measure the fit on.
+    pub validation: f64,
+    // the heuristic value at which X is e times as likely to win as to lose.
+    pub scale: f64,
+    pub method: Method,
+    pub seed: u64,
+}
+
+impl TunerConfig {
+    pub fn new(games: usize, method: Method, seed: u64) -> Self {
+        Self {
+            games,
+            validation: 0.1,
+            scale: 1000.0,
+            method,
+            seed,
+        }
+    }
+
+    pub fn with_validation(self, validation: f64) -> Self {
+        Self { validation, ..self }
+    }
+
+    pub fn with_scale(self, scale: f64) -> Self {
+        Self { scale, ..self }
+    }
+}
+
+// the positions of one game, before each roll in a game of chance and
+// before each move in a game without, and its result for X.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trajectory {
+    pub positions: Vec<Vec<f64>>,
+    pub result: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuneReport {
+    pub weights: Vec<f64>,
+    pub positions: usize,
+    // the mean log loss of the predictions, on the games learned from
+    // and on the games held back, with the weights before and after tuning.
+    pub initial_train_loss: f64,
+    pub train_loss: f64,
+    pub initial_validation_loss: f64,
+    pub validation_loss: f64,
+}
+
+// an agent that passes its choices on to another, and keeps the features
+// of every position before a roll, or before every move if `every_move`.
+struct Recorder<P> {
+    policy: P,
+    every_move: bool,
+    positions: Vec<Vec<f64>>,
+}
+
+impl<G: Tunable, P: Policy<G>> Policy<G> for Recorder<P> {
+    fn choose<R: Rng>(&mut self, game: &G, moves: &[G::Move], rng: &mut R) -> G::Move {
+        self.policy.choose(game, moves, rng)
+    }
+
+    fn observe(&mut self, game: &G, m: G::Move) {
+        if self.every_move || game.to_move() == ToMove::Chance {
+            self.positions.push(game.feature_vector());
+        }
+        self.policy.observe(game, m);
+    }
+}
+
+// plays games of chance with the policy on both sides, each seeded from
+// `seed`. games that get stuck without a result are left out.
+pub fn self_play_games<G, P>(game: &G, policy: &P, games: usize, seed: u64) -> Vec<Trajectory>
+where
+    G: StochasticGame + Tunable,
+    P: Policy<G> + Clone,
+{
+    self_play_by(game, policy, games, seed, false, driver::play)
+}
+
+// the same for games without chance, which keeps every position.
+pub fn self_play_games_without_chance<G, P>(game: &G, policy: &P, games: usize, seed: u64) -> Vec<Trajectory>
+where
+    G: Tunable,
+    P: Policy<G> + Clone,
+{
+    self_play_by(game, policy, games, seed, true, driver::play_without_chance)
+}
+
+fn self_play_by<G, P, F>(game: &G, policy: &P, games: usize, seed: u64, every_move: bool, play: F) -> Vec<Trajectory>
+where
+    G: Tunable,
+    P: Policy<G> + Clone,
+    F: Fn(G, &mut Recorder<P>, &mut P, u64) -> (G, GameRecord),
+{
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut trajectories = Vec::with_capacity(games);
+    for _ in 0..games {
+        let mut recorder = Recorder { policy: policy.clone(), every_move, positions: Vec::new() };
+        let (end, _) = play(game.clone(), &mut recorder, &mut policy.clone(), rng.gen());
+        let positions = recorder.positions;
+        if let Some(outcome) = end.outcome() {
+            let result = match outcome.sign() {
+                1 => 1.0,
+                -1 => 0.0,
+                _ => 0.5,
+            };
+            trajectories.push(Trajectory { positions, result });
+        }
+    }
+    trajectories
+}
+
+pub fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// the mean log loss of predicting each position's result
+// from the weighted features, over every position in the games.
+pub fn log_loss(games: &[Trajectory], weights: &[f64], scale: f64) -> f64 {
+    let mut total = 0.0;
+    let mut count = 0;
+    for game in games {
+        for features in &game.positions {
+            let p = sigmoid(dot(weights, features) / scale).clamp(1e-12, 1.0 - 1e-12);
+            total -= game.result * p.ln() + (1.0 - game.result) * (1.0 - p).ln();
+            count += 1;
+        }
+    }
+    total / count.max(1) as f64
+}
+
+// the fits work with each feature divided by its root mean square over the
+// games, and weights in units of the scale, so that one step size suits
+// features of every size. this gives the divisor of each feature.
+fn feature_scales(games: &[Trajectory], features: usize) -> Vec<f64> {
+    let mut squares = vec![0.0; features];
+    let mut count = 0;
+    for position in games.iter().flat_map(|game| &game.positions) {
+        for (square, x) in squares.iter_mut().zip(position) {
+            *square += x * x;
+        }
+        count += 1;
+    }
+    squares
+        .into_iter()
+        .map(|square| (square / count.max(1) as f64).sqrt())
+        .map(|rms| if rms > 0.0 { rms } else { 1.0 })
+        .collect()
+}
+
+// solves a x = b by Gaussian elimination with partial pivoting.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs())).unwrap();
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..n {
+            let factor = a[row][col] / a[col][col];
+            let (above, below) = a.split_at_mut(row);
+            for (x, p) in below[0][col..].iter_mut().zip(&above[col][col..]) {
+                *x -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let rest = (row + 1..n).map(|k| a[row][k] * x[k]).sum::<f64>();
+        x[row] = (b[row] - rest) / a[row][row];
+    }
+    x
+}
+
+// fits the weights that minimise the log loss on the games, starting from
+// `initial`. a little ridge regularisation keeps features that never vary
+// from making the problem singular.
+pub fn fit_texel(games: &[Trajectory], initial: &[f64], scale: f64, iterations: usize) -> Vec<f64> {
+    const RIDGE: f64 = 1e-6;
+    let n = initial.len();
+    let scales = feature_scales(games, n);
+    let mut u = initial.iter().zip(&scales).map(|(w, s)| w * s / scale).collect::<Vec<_>>();
+    for _ in 0..iterations {
+        let mut gradient = u.iter().map(|&ui| RIDGE * ui).collect::<Vec<_>>();
+        let mut hessian = (0..n).map(|i| (0..n).map(|j| if i == j { RIDGE } else { 0.0 }).collect::<Vec<_>>()).collect::<Vec<_>>();
+        for game in games {
+            for position in &game.positions {
+                let x = position.iter().zip(&scales).map(|(f, s)| f / s).collect::<Vec<_>>();
+                let p = sigmoid(dot(&u, &x));
+                for i in 0..n {
+                    gradient[i] += (p - game.result) * x[i];
+                    for j in 0..n {
+                        hessian[i][j] += p * (1.0 - p) * x[i] * x[j];
+                    }
+                }
+            }
+        }
+        let step = solve(hessian, gradient);
+        for (ui, di) in u.iter_mut().zip(&step) {
+            *ui -= di;
+        }
+        if step.iter().all(|d| d.abs() < 1e-9) {
+            break;
+        }
+    }
+    u.iter().zip(&scales).map(|(ui, s)| ui * scale / s).collect()
+}
+
+// fits the weights by TD(λ), going through the games in order `epochs` times.
+pub fn fit_td(games: &[Trajectory], initial: &[f64], scale: f64, lambda: f64, alpha: f64, epochs: usize) -> Vec<f64> {
+    let n = initial.len();
+    let scales = feature_scales(games, n);
+    let mut u = initial.iter().zip(&scales).map(|(w, s)| w * s / scale).collect::<Vec<_>>();
+    let normalise = |position: &Vec<f64>| position.iter().zip(&scales).map(|(f, s)| f / s).collect::<Vec<_>>();
+    for _ in 0..epochs {
+        for game in games {
+            let mut trace = vec![0.0; n];
+            for (t, position) in game.positions.iter().enumerate() {
+                let x = normalise(position);
+                let value = sigmoid(dot(&u, &x));
+                let next = match game.positions.get(t + 1) {
+                    Some(next) => sigmoid(dot(&u, &normalise(next))),
+                    None => game.result,
+                };
+                for (e, xi) in trace.iter_mut().zip(&x) {
+                    *e = lambda * *e + value * (1.0 - value) * xi;
+                }
+                for (ui, e) in u.iter_mut().zip(&trace) {
+                    *ui += alpha * (next - value) * e;
+                }
+            }
+        }
+    }
+    u.iter().zip(&scales).map(|(ui, s)| ui * scale / s).collect()
+}
+
+// plays `config.games` games of chance with the policy, fits the game's
+// weights to all but the validation games, and reports how well they
+// predict. the weights are not set on the game.
+pub fn tune<G, P>(game: &G, policy: &P, config: TunerConfig) -> TuneReport
+where
+    G: StochasticGame + Tunable,
+    P: Policy<G> + Clone,
+{
+    fit(game, &self_play_games(game, policy, config.games, config.seed), config)
+}
+
+// the same for games without chance.
+pub fn tune_without_chance<G, P>(game: &G, policy: &P, config: TunerConfig) -> TuneReport
+where
+    G: Tunable,
+    P: Policy<G> + Clone,
+{
+    fit(game, &self_play_games_without_chance(game, policy, config.games, config.seed), config)
+}
+
+fn fit<G: Tunable>(game: &G, games: &[Trajectory], config: TunerConfig) -> TuneReport {
+    let held_back = ((games.len() as f64 * config.validation).round() as usize).min(games.len());
+    let (train, validation) = games.split_at(games.len() - held_back);
+    let initial = game.weights();
+    let weights = match config.method {
+        Method::Texel { iterations } => fit_texel(train, &initial, config.scale, iterations),
+        Method::TemporalDifference { lambda, alpha, epochs } => fit_td(train, &initial, config.scale, lambda, alpha, epochs),
+    };
+    TuneReport {
+        positions: train.iter().map(|game| game.positions.len()).sum(),
+        initial_train_loss: log_loss(train, &initial, config.scale),
+        train_loss: log_loss(train, &weights, config.scale),
+        initial_validation_loss: log_loss(validation, &initial, config.scale),
+        validation_loss: log_loss(validation, &weights, config.scale),
+        weights,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::{self, Display, Formatter};
+    use std::path::Path;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::game::{Game, Outcome, PartiallySolvable, Player, ToMove, Tunable};
+    use crate::rgu::Ur;
+    use crate::ureval::UrWeights;
+    use crate::winprob::{GreedyPolicy, RandomPolicy};
+
+    use super::{fit_td, fit_texel, log_loss, tune, tune_without_chance, Method, Trajectory, TunerConfig};
+
+    // Nim with one heap, taking one or two stones a turn. whoever takes the
+    // last stone wins, so the player to move loses when the heap is a
+    // multiple of three, and the one feature says so from X's side.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Nim {
+        stones: u8,
+        moves: u8,
+        weight: i32,
+    }
+
+    impl Display for Nim {
+        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+            write!(f, "{} stones", self.stones)
+        }
+    }
+
+    impl Game for Nim {
+        type Move = u8;
+
+        fn turn(&self) -> i8 {
+            if self.moves & 1 == 0 { 1 } else { -1 }
+        }
+
+        fn outcome(&self) -> Option<Outcome> {
+            let last = if self.turn() == 1 { Player::O } else { Player::X };
+            (self.stones == 0).then_some(Outcome::Win(last))
+        }
+
+        fn generate_moves(&self, buffer: &mut Vec<u8>) {
+            buffer.extend((1..=2).filter(|&take| take <= self.stones));
+        }
+
+        fn push(&mut self, m: u8) {
+            self.stones -= m;
+            self.moves += 1;
+        }
+
+        fn pop(&mut self, m: u8) {
+            self.stones += m;
+            self.moves -= 1;
+        }
+
+        fn action_space_size(&self) -> usize {
+            2
+        }
+
+        fn to_move(&self) -> ToMove {
+            if self.turn() == 1 { ToMove::Max } else { ToMove::Min }
+        }
+
+        fn move_index(&self, m: u8) -> usize {
+            m as usize - 1
+        }
+    }
+
+    impl PartiallySolvable for Nim {
+        fn heuristic(&self) -> i32 {
+            self.weight * self.feature_vector()[0] as i32
+        }
+    }
+
+    impl Tunable for Nim {
+        fn feature_names(&self) -> &'static [&'static str] {
+            &["winning"]
+        }
+
+        fn feature_vector(&self) -> Vec<f64> {
+            let turn = self.turn() as f64;
+            // the side to move loses on a multiple of three.
+            let to_move_wins = match self.stones % 3 {
+                0 => -1.0,
+                _ => 1.0,
+            };
+            vec![to_move_wins * turn]
+        }
+
+        fn weights(&self) -> Vec<f64> {
+            vec![self.weight as f64]
+        }
+
+        fn set_weights(&mut self, weights: &[f64]) {
+            self.weight = weights[0].round() as i32;
+        }
+
+        fn save_weights(&self, path: &Path) -> std::io::Result<()> {
+            std::fs::write(path, format!("winning {}\n", self.weight))
+        }
+    }
+
+    // positions with two features, won with the probability
+    // that a logistic model with the given weights predicts.
+    fn synthetic(weights: [f64; 2], scale: f64, samples: usize) -> Vec<Trajectory> {
+        let mut rng = StdRng::seed_from_u64(3);
+        (0..samples)
+            .map(|_| {
+                let features = vec![rng.gen_range(-10.0..10.0), rng.gen_range(0.0..1.0)];
+                let p = super::sigmoid(super::dot(&weights, &features) / scale);
+                Trajectory { positions: vec![features], result: if rng.gen_bool(p) { 1.0 } else { 0.0 } }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn texel_recovers_weights() {
+        let games = synthetic([100.0, -800.0], 1000.0, 20_000);
+        let weights = fit_texel(&games, &[0.0, 0.0], 1000.0, 20);
+        assert!((weights[0] - 100.0).abs() < 10.0, "{:?}", weights);
+        assert!((weights[1] + 800.0).abs() < 100.0, "{:?}", weights);
+        assert!(log_loss(&games, &weights, 1000.0) < log_loss(&games, &[0.0, 0.0], 1000.0));
+    }
+
+    #[test]
+    fn td_improves_on_a_poor_start() {
+        let games = synthetic([100.0, -800.0], 1000.0, 5_000);
+        let start = [-50.0, 500.0];
+        let weights = fit_td(&games, &start, 1000.0, 0.7, 0.05, 5);
+        assert!(log_loss(&games, &weights, 1000.0) < log_loss(&games, &start, 1000.0));
+        assert!(weights[0] > 0.0 && weights[1] < 0.0, "{:?}", weights);
+    }
+
+    #[test]
+    fn tune_ur() {
+        let config = TunerConfig::new(30, Method::Texel { iterations: 10 }, 8);
+        let report = tune(&Ur::new(), &GreedyPolicy, config);
+        assert_eq!(report.weights.len(), Ur::new().feature_names().len());
+        assert!(report.positions > 0);
+        assert!(report.train_loss <= report.initial_train_loss);
+        assert!(report.validation_loss.is_finite());
+        // the same seed gives the same games, and so the same fit.
+        assert_eq!(tune(&Ur::new(), &GreedyPolicy, config), report);
+
+        let path = std::env::temp_dir().join(format!("ur-tuned-{}.txt", std::process::id()));
+        let mut tuned = Ur::new();
+        tuned.set_weights(&report.weights);
+        tuned.save_weights(&path).unwrap();
+        let loaded = UrWeights::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tuned, Ur::new().with_weights(loaded));
+    }
+
+    #[test]
+    fn tune_without_chance_keeps_every_position() {
+        let nim = Nim { stones: 10, moves: 0, weight: 0 };
+        let config = TunerConfig::new(200, Method::Texel { iterations: 10 }, 4).with_validation(0.0);
+        let report = tune_without_chance(&nim, &RandomPolicy, config);
+        // every game takes between five and ten moves.
+        assert!((5 * 200..=10 * 200).contains(&report.positions), "{}", report.positions);
+        // whose turn it is on a multiple of three decides the game with
+        // perfect play, and tilts it with random play.
+        assert!(report.weights[0] > 0.0, "{:?}", report.weights);
+        assert!(report.train_loss < report.initial_train_loss);
+    }
+}
@@ -0,0 +1,118 @@
+use std::{fmt::{self, Display, Formatter}, path::Path};
+
+use crate::io::{self, LoadError, ParseError};
+
+// the features of an Ur position that the heuristic weighs, by name. each
+// is measured for X less the same measure for O.
+pub const FEATURES: [&str; 6] = [
+    // the sum of the squares the pieces on the board have reached.
+    "progress",
+    // pieces waiting in the pot.
+    "in_pot",
+    // pieces borne off.
+    "borne_off",
+    // pieces sitting on rosettes.
+    "rosettes",
+    // the expected number of pieces the opponent can capture with their next
+    // roll: for each piece, the chance of a roll that brings an opposing
+    // piece, on the board or in the pot, onto its square.
+    "threatened",
+    // holding the rosette in the middle of the board.
+    "central_rosette",
+];
+
+pub type Features = [f64; FEATURES.len()];
+
+// the weight of each feature in the heuristic, in the order of FEATURES.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UrWeights(pub [i32; FEATURES.len()]);
+
+impl Default for UrWeights {
+    fn default() -> Self {
+        Self([100, -50, 2000, 100, -300, 150])
+    }
+}
+
+impl UrWeights {
+    // the heuristic value of a position with these features, from X's side.
+    pub fn evaluate(&self, features: &Features) -> f64 {
+        self.0.iter().zip(features).map(|(&w, &f)| w as f64 * f).sum()
+    }
+
+    pub fn get(&self, name: &str) -> Option<i32> {
+        FEATURES.iter().position(|&f| f == name).map(|i| self.0[i])
+    }
+
+    // reads weights from text with one "name value" line per feature. blank
+    // lines and comments after a '#' are skipped, and features that aren't
+    // given keep their default weights.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let err = |line, message: String| ParseError { line, message };
+        let mut weights = Self::default();
+        for (line, text) in text.lines().enumerate().map(|(i, l)| (i + 1, l)) {
+            let text = text.split('#').next().unwrap().trim();
+            if text.is_empty() {
+                continue;
+            }
+            let (name, value) = text
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| err(line, "expected a feature name and a weight".to_string()))?;
+            let index = FEATURES
+                .iter()
+                .position(|&f| f == name)
+                .ok_or_else(|| err(line, format!("unknown feature \"{}\"", name)))?;
+            weights.0[index] = value.trim().parse().map_err(|_| err(line, format!("invalid weight for {}", name)))?;
+        }
+        Ok(weights)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        io::load(path, Self::parse)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl Display for UrWeights {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (name, weight) in FEATURES.iter().zip(self.0) {
+            writeln!(f, "{} {}", name, weight)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UrWeights, FEATURES};
+
+    #[test]
+    fn parse() {
+        let weights = UrWeights::default();
+        assert_eq!(UrWeights::parse(&weights.to_string()).unwrap(), weights);
+
+        let text = "# more cautious play\nthreatened -800\n\nrosettes   250  # hold them\n";
+        let parsed = UrWeights::parse(text).unwrap();
+        assert_eq!(parsed.get("threatened"), Some(-800));
+        assert_eq!(parsed.get("rosettes"), Some(250));
+        assert_eq!(parsed.get("progress"), weights.get("progress"));
+        assert_eq!(parsed.get("luck"), None);
+
+        assert_eq!(UrWeights::parse("progress 1\nluck 3").unwrap_err().line, 2);
+        assert_eq!(UrWeights::parse("progress").unwrap_err().line, 1);
+        assert_eq!(UrWeights::parse("progress lots").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn load_and_save() {
+        let path = std::env::temp_dir().join(format!("ur-weights-{}.txt", std::process::id()));
+        let mut weights = UrWeights::default();
+        weights.0[FEATURES.len() - 1] = 7;
+        weights.save(&path).unwrap();
+        assert_eq!(UrWeights::load(&path).unwrap(), weights);
+        std::fs::remove_file(&path).unwrap();
+        assert!(UrWeights::load(&path).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    path::Path,
+    sync::Arc,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    game::{Game, Outcome, Player, StochasticGame, ToMove},
+    io::{load, LoadError, ParseError},
+    rgu::{Ur, ValueNet, ENCODED_LEN},
+    solver::{expecti_best_move, ExpectiConfig},
+    tuner::sigmoid,
+    ureval::UrWeights,
+    winprob::Policy,
+};
+
+// a learned value function for Ur, in the manner of TD-Gammon: a network
+// with one hidden layer reads the encoded position and gives the chance
+// that X wins, and it learns by TD(λ) from games against itself.
+
+// a multi-layer perceptron with one hidden layer, sigmoid units throughout,
+// and a single output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mlp {
+    inputs: usize,
+    hidden: usize,
+    // the hidden weights, row by row, then the hidden biases,
+    // the output weights and the output bias.
+    params: Vec<f64>,
+}
+
+impl Mlp {
+    // a network with random weights of up to a half, enough for the
+    // hidden units to differ from the start.
+    pub fn new<R: Rng>(inputs: usize, hidden: usize, rng: &mut R) -> Self {
+        let count = hidden * inputs + hidden + hidden + 1;
+        let params = (0..count).map(|_| rng.gen_range(-0.5..0.5)).collect();
+        Self { inputs, hidden, params }
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    fn hidden_layer(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.inputs);
+        let (weights, rest) = self.params.split_at(self.hidden * self.inputs);
+        weights
+            .chunks(self.inputs)
+            .zip(&rest[..self.hidden])
+            .map(|(row, bias)| sigmoid(row.iter().zip(x).map(|(w, xi)| w * xi).sum::<f64>() + bias))
+            .collect()
+    }
+
+    fn output(&self, h: &[f64]) -> f64 {
+        let rest = &self.params[self.hidden * self.inputs + self.hidden..];
+        sigmoid(rest[..self.hidden].iter().zip(h).map(|(w, hj)| w * hj).sum::<f64>() + rest[self.hidden])
+    }
+
+    pub fn forward(&self, x: &[f64]) -> f64 {
+        self.output(&self.hidden_layer(x))
+    }
+
+    // the output, and its gradient with respect to every parameter.
+    fn gradient(&self, x: &[f64]) -> (f64, Vec<f64>) {
+        let h = self.hidden_layer(x);
+        let y = self.output(&h);
+        let dy = y * (1.0 - y);
+        let output_weights = &self.params[self.hidden * self.inputs + self.hidden..][..self.hidden];
+        let mut grad = Vec::with_capacity(self.params.len());
+        // the hidden units' deltas, which their weights and biases share.
+        let deltas = h.iter().zip(output_weights).map(|(hj, w)| dy * w * hj * (1.0 - hj)).collect::<Vec<_>>();
+        for delta in &deltas {
+            grad.extend(x.iter().map(|xi| delta * xi));
+        }
+        grad.extend(&deltas);
+        grad.extend(h.iter().map(|hj| dy * hj));
+        grad.push(dy);
+        (y, grad)
+    }
+
+    // reads a network written by Display: a header line "mlp <inputs> <hidden>",
+    // then the parameters, separated by whitespace.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let err = |line, message: &str| ParseError { line, message: message.to_string() };
+        let mut lines = text.lines();
+        let header = lines.next().unwrap_or("").split_whitespace().collect::<Vec<_>>();
+        let (inputs, hidden) = match header[..] {
+            ["mlp", inputs, hidden] => (
+                inputs.parse::<usize>().map_err(|_| err(1, "invalid input count"))?,
+                hidden.parse::<usize>().map_err(|_| err(1, "invalid hidden count"))?,
+            ),
+            _ => return Err(err(1, "expected \"mlp <inputs> <hidden>\"")),
+        };
+        let mut params = Vec::new();
+        for (line, text) in lines.enumerate().map(|(i, l)| (i + 2, l)) {
+            for word in text.split_whitespace() {
+                params.push(word.parse().map_err(|_| err(line, "invalid parameter"))?);
+            }
+        }
+        if params.len() != hidden * inputs + 2 * hidden + 1 {
+            return Err(err(1, "wrong number of parameters"));
+        }
+        Ok(Self { inputs, hidden, params })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        load(path, Self::parse)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl Display for Mlp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // a line for each hidden unit's weights, then one each for the
+        // hidden biases, the output weights and the output bias.
+        writeln!(f, "mlp {} {}", self.inputs, self.hidden)?;
+        let (weights, rest) = self.params.split_at(self.hidden * self.inputs);
+        for row in weights.chunks(self.inputs).chain(rest.chunks(self.hidden)) {
+            let row = row.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl ValueNet for Mlp {
+    fn forward(&self, x: &[f64]) -> f64 {
+        Mlp::forward(self, x)
+    }
+}
+
+// the chance that X wins: the result, if the game is over, and otherwise
+// the network's estimate.
+pub fn value(net: &Mlp, game: &Ur) -> f64 {
+    match game.outcome() {
+        Some(Outcome::Win(Player::X)) => 1.0,
+        Some(Outcome::Win(Player::O)) => 0.0,
+        Some(_) => 0.5,
+        None => net.forward(&game.encode()),
+    }
+}
+
+// the move that leaves the position the network rates best for the side to move.
+fn greedy_move(net: &Mlp, game: &mut Ur, moves: &[<Ur as Game>::Move]) -> <Ur as Game>::Move {
+    let sign = game.turn() as f64;
+    let mut best = (moves[0], f64::NEG_INFINITY);
+    for &m in moves {
+        game.push(m);
+        let v = value(net, game) * sign;
+        game.pop(m);
+        if v > best.1 {
+            best = (m, v);
+        }
+    }
+    best.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TdConfig {
+    pub games: usize,
+    pub lambda: f64,
+    pub alpha: f64,
+    pub seed: u64,
+}
+
+// trains the network by TD(λ) on games that it plays against itself, moving
+// greedily by its own estimates. the estimate of each position before a roll
+// is moved towards the next one's, and the last towards the result.
+pub fn train(net: &mut Mlp, game: &Ur, config: TdConfig) {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut moves = Vec::with_capacity(game.action_space_size());
+    for _ in 0..config.games {
+        let mut game = game.clone();
+        let mut trace = vec![0.0; net.params.len()];
+        let (mut estimate, mut grad) = net.gradient(&game.encode());
+        while !game.is_terminal() {
+            match game.to_move() {
+                ToMove::Chance => {
+                    let roll = game.sample_chance_move(&mut rng);
+                    game.push(roll);
+                },
+                ToMove::Max | ToMove::Min => {
+                    moves.clear();
+                    game.generate_moves(&mut moves);
+                    let m = greedy_move(net, &mut game, &moves);
+                    game.push(m);
+                    for (e, g) in trace.iter_mut().zip(&grad) {
+                        *e = config.lambda * *e + g;
+                    }
+                    let next = if game.is_terminal() {
+                        value(net, &game)
+                    } else {
+                        net.forward(&game.encode())
+                    };
+                    let step = config.alpha * (next - estimate);
+                    for (p, e) in net.params.iter_mut().zip(&trace) {
+                        *p += step * e;
+                    }
+                    (estimate, grad) = net.gradient(&game.encode());
+                },
+            }
+        }
+    }
+}
+
+// plays Ur with an expectimax search, judging positions by the network if it
+// has one, and by the handcrafted heuristic with the given weights if not.
+#[derive(Debug, Clone)]
+pub struct UrEngine {
+    pub config: ExpectiConfig,
+    pub net: Option<Arc<Mlp>>,
+    pub weights: UrWeights,
+}
+
+impl Policy<Ur> for UrEngine {
+    fn choose<R: Rng>(&mut self, game: &Ur, moves: &[<Ur as Game>::Move], _rng: &mut R) -> <Ur as Game>::Move {
+        let game = match &self.net {
+            Some(net) => game.clone().with_net(net.clone()),
+            None => game.clone().without_net().with_weights(self.weights),
+        };
+        expecti_best_move(game, self.config).map_or(moves[0], |result| result.best_move)
+    }
+}
+
+// a fresh network sized for Ur's encoding.
+pub fn new_ur_net<R: Rng>(hidden: usize, rng: &mut R) -> Mlp {
+    Mlp::new(ENCODED_LEN, hidden, rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::driver::play_match;
+    use crate::game::{PartiallySolvable, Player};
+    use crate::rgu::Ur;
+    use crate::solver::ExpectiConfig;
+    use crate::testing::{assert_gradient_matches, assert_round_trip};
+    use crate::ureval::UrWeights;
+    use crate::winprob::RandomPolicy;
+
+    use super::{new_ur_net, train, value, Mlp, TdConfig, UrEngine};
+
+    #[test]
+    fn gradient_matches_differences() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let net = Mlp::new(5, 4, &mut rng);
+        let x = [0.5, -1.0, 0.0, 2.0, 1.0];
+        let (y, grad) = net.gradient(&x);
+        assert_eq!(y, net.forward(&x));
+        let forward = |params: &[f64]| Mlp { params: params.to_vec(), ..net.clone() }.forward(&x);
+        assert_gradient_matches(&net.params, &grad, forward, 1e-6);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let net = new_ur_net(8, &mut rng);
+        assert_round_trip(&net, Mlp::parse);
+        assert!(Mlp::parse("mlp 2 1\n1 2 3").is_err());
+        assert!(Mlp::parse("net 2 1").is_err());
+    }
+
+    #[test]
+    fn learns_that_bearing_off_wins() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut net = new_ur_net(16, &mut rng);
+        // a short race, so that the results reach back quickly.
+        let race = Ur::endgame(&[2, 5], &[1, 4], Player::X);
+        train(&mut net, &race, TdConfig { games: 300, lambda: 0.7, alpha: 0.5, seed: 4 });
+        // X, to roll, with one piece left to bear off, against O with two.
+        let ahead = Ur::endgame(&[12], &[2, 5], Player::X);
+        let behind = Ur::endgame(&[2, 5], &[12], Player::X);
+        assert!(value(&net, &ahead) > 0.5, "{}", value(&net, &ahead));
+        assert!(value(&net, &behind) < 0.5, "{}", value(&net, &behind));
+
+        // and plugged in as the heuristic, it keeps the same order.
+        let net = Arc::new(net);
+        let ahead = ahead.with_net(net.clone());
+        let behind = behind.with_net(net);
+        assert!(ahead.heuristic() > 0 && behind.heuristic() < 0);
+        assert_eq!(ahead.clone(), ahead);
+    }
+
+    #[test]
+    fn match_against_random_play() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let net = Arc::new(new_ur_net(4, &mut rng));
+        let mut engine = UrEngine { config: ExpectiConfig::new(1), net: Some(net), weights: UrWeights::default() };
+        let score = play_match(&Ur::new(), &mut engine, &mut RandomPolicy, 2, 6);
+        assert_eq!(score.games(), 4);
+        assert_eq!(play_match(&Ur::new(), &mut engine, &mut RandomPolicy, 2, 6), score);
+    }
+
+    #[test]
+    fn heuristic_beats_random_play() {
+        let mut engine = UrEngine { config: ExpectiConfig::new(1), net: None, weights: UrWeights::default() };
+        let score = play_match(&Ur::new(), &mut engine, &mut RandomPolicy, 10, 7);
+        assert!(score.wins >= 16, "{}", score);
+        // the wins follow the engine when it's named second, though it
+        // still plays X and O in turn.
+        let score = play_match(&Ur::new(), &mut RandomPolicy, &mut engine, 10, 7);
+        assert!(score.losses >= 16, "{}", score);
+    }
+}
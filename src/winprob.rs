@@ -0,0 +1,494 @@
+use std::{collections::{HashMap, VecDeque}, fmt::{self, Display, Formatter}};
+
+use rand::Rng;
+
+use crate::{
+    game::{Game, Keyed, PartiallySolvable, Player, StochasticGame, ToMove},
+    solver::{expecti_best_move, ExpectiConfig},
+};
+
+// win probabilities for games of chance, such as Ur.
+// small positions, like endgames, are solved exactly for perfect play by
+// both sides. anything bigger is estimated by playing games out to the end
+// with a chosen policy, so the answer is only as good as the policy.
+
+// the 95% two-sided normal quantile.
+const Z: f64 = 1.96;
+// the exact solve stops improving its values once no value changes by more than this.
+const TOLERANCE: f64 = 1e-12;
+const MAX_SWEEPS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    // solved by value iteration over every position reachable from this one.
+    Exact { states: usize },
+    // the mean result of this many games played out with the policy.
+    MonteCarlo { rollouts: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WinProbability {
+    // the chance that X wins, counting drawn games as half a win.
+    pub x_wins: f64,
+    // a 95% confidence interval around x_wins.
+    // an exact solve has no uncertainty, so both ends are x_wins.
+    pub low: f64,
+    pub high: f64,
+    pub method: Method,
+}
+
+impl WinProbability {
+    fn exact(x_wins: f64, states: usize) -> Self {
+        Self {
+            x_wins,
+            low: x_wins,
+            high: x_wins,
+            method: Method::Exact { states },
+        }
+    }
+
+    // the Wilson score interval, which behaves well near 0% and 100%,
+    // where the normal approximation falls outside the range.
+    fn from_rollouts(total: f64, rollouts: u64) -> Self {
+        let n = rollouts as f64;
+        let p = total / n;
+        let denominator = 1.0 + Z * Z / n;
+        let centre = (p + Z * Z / (2.0 * n)) / denominator;
+        let half_width = Z / denominator * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt();
+        Self {
+            x_wins: p,
+            low: (centre - half_width).max(0.0),
+            high: (centre + half_width).min(1.0),
+            method: Method::MonteCarlo { rollouts },
+        }
+    }
+
+    // the chance that the given player wins.
+    pub fn for_player(self, player: Player) -> f64 {
+        match player {
+            Player::X => self.x_wins,
+            Player::O => 1.0 - self.x_wins,
+        }
+    }
+}
+
+impl Display for WinProbability {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "X wins {:.1}%", self.x_wins * 100.0)?;
+        match self.method {
+            Method::Exact { .. } => write!(f, " (exact)"),
+            Method::MonteCarlo { rollouts } => write!(
+                f,
+                " (95% CI {:.1}% to {:.1}%, {} rollouts)",
+                self.low * 100.0,
+                self.high * 100.0,
+                rollouts,
+            ),
+        }
+    }
+}
+
+// how a player picks moves when games are played out.
+pub trait Policy<G: Game> {
+    // moves is never empty.
+    fn choose<R: Rng>(&mut self, game: &G, moves: &[G::Move], rng: &mut R) -> G::Move;
+
+    // called with every move played in a game, by either side or the dice,
+    // before it is pushed. the default ignores them.
+    fn observe(&mut self, _game: &G, _m: G::Move) {}
+
+    // how strongly the policy favours each of the moves, as probabilities
+    // in the same order. the default puts everything on the chosen move;
+    // searches that weigh every move, like MCTS, say how they weighed them.
+    fn distribution<R: Rng>(&mut self, game: &G, moves: &[G::Move], rng: &mut R) -> Vec<f64> {
+        let choice = self.choose(game, moves, rng);
+        let index = game.move_index(choice);
+        moves.iter().map(|&m| if game.move_index(m) == index { 1.0 } else { 0.0 }).collect()
+    }
+}
+
+// draws a move with the probabilities of the distribution.
+pub fn sample_move<M: Copy, R: Rng>(moves: &[M], distribution: &[f64], rng: &mut R) -> M {
+    let mut x = rng.gen::<f64>() * distribution.iter().sum::<f64>();
+    for (&m, &p) in moves.iter().zip(distribution) {
+        if x < p {
+            return m;
+        }
+        x -= p;
+    }
+    favourite_move(moves, distribution, rng)
+}
+
+// the most likely move, with ties broken at random.
+pub fn favourite_move<M: Copy, R: Rng>(moves: &[M], distribution: &[f64], rng: &mut R) -> M {
+    let most = distribution.iter().copied().fold(f64::MIN, f64::max);
+    let best = moves.iter().zip(distribution).filter(|&(_, &p)| p == most).map(|(&m, _)| m).collect::<Vec<_>>();
+    best[rng.gen_range(0..best.len())]
+}
+
+// picks uniformly at random.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomPolicy;
+
+impl<G: Game> Policy<G> for RandomPolicy {
+    fn choose<R: Rng>(&mut self, _game: &G, moves: &[G::Move], rng: &mut R) -> G::Move {
+        moves[rng.gen_range(0..moves.len())]
+    }
+
+    fn distribution<R: Rng>(&mut self, _game: &G, moves: &[G::Move], _rng: &mut R) -> Vec<f64> {
+        vec![1.0 / moves.len() as f64; moves.len()]
+    }
+}
+
+// picks the move that leaves the best heuristic value for the side to move.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GreedyPolicy;
+
+impl<G: PartiallySolvable> Policy<G> for GreedyPolicy {
+    fn choose<R: Rng>(&mut self, game: &G, moves: &[G::Move], _rng: &mut R) -> G::Move {
+        let mut game = game.clone();
+        let sign = game.turn() as i64;
+        *moves
+            .iter()
+            .max_by_key(|&&m| {
+                game.push(m);
+                let value = game.heuristic() as i64 * sign;
+                game.pop(m);
+                value
+            })
+            .unwrap()
+    }
+}
+
+// picks the move that an expectimax search prefers.
+#[derive(Debug, Clone, Copy)]
+pub struct ExpectiPolicy(pub ExpectiConfig);
+
+impl<G: StochasticGame + PartiallySolvable> Policy<G> for ExpectiPolicy {
+    fn choose<R: Rng>(&mut self, game: &G, moves: &[G::Move], _rng: &mut R) -> G::Move {
+        expecti_best_move(game.clone(), self.0).map_or(moves[0], |result| result.best_move)
+    }
+}
+
+// 1 if X won, 0 if O won, and a half for a draw.
+pub fn result_for_x<G: Game>(game: &G) -> Option<f64> {
+    game.outcome().map(|outcome| match outcome.winner() {
+        Some(Player::X) => 1.0,
+        Some(Player::O) => 0.0,
+        None => 0.5,
+    })
+}
+
+// plays the game out to the end with the policy for both sides, and
+// returns the result for X. a game that is stuck, with no moves for the
+// side to move, can't be won by either side, so it is counted as a draw.
+pub fn rollout<G, P, R>(game: &G, policy: &mut P, rng: &mut R) -> f64
+where
+    G: StochasticGame,
+    P: Policy<G>,
+    R: Rng,
+{
+    let mut game = game.clone();
+    let mut moves = Vec::with_capacity(game.action_space_size());
+    loop {
+        if let Some(result) = result_for_x(&game) {
+            return result;
+        }
+        let m = match game.to_move() {
+            ToMove::Chance => game.sample_chance_move(rng),
+            ToMove::Max | ToMove::Min => {
+                moves.clear();
+                game.generate_moves(&mut moves);
+                if moves.is_empty() {
+                    return 0.5;
+                }
+                policy.choose(&game, &moves, rng)
+            }
+        };
+        game.push(m);
+    }
+}
+
+enum NodeKind {
+    Resolved(f64),
+    Max,
+    Min,
+    Chance,
+}
+
+struct Node {
+    kind: NodeKind,
+    // indices of the child nodes, with their probabilities at chance nodes.
+    children: Vec<(usize, f64)>,
+}
+
+// the chance that X wins with perfect play, for every position reachable from
+// a root position. the positions form a graph rather than a tree, with cycles
+// where pieces are captured and sent back, so the values are found by value
+// iteration: starting from zero, every position is repeatedly updated from its
+// children until nothing changes.
+pub struct ExactSolution {
+    index: HashMap<u64, usize>,
+    values: Vec<f64>,
+}
+
+impl ExactSolution {
+    // returns None if more than max_states positions are reachable.
+    pub fn solve<G: StochasticGame + Keyed>(game: &G, max_states: usize) -> Option<Self> {
+        let mut index = HashMap::new();
+        let mut nodes = Vec::new();
+        let mut queue = VecDeque::new();
+        let mut buffer = Vec::new();
+        let mut outcomes = Vec::new();
+
+        index.insert(game.hashkey(), 0);
+        nodes.push(Node { kind: NodeKind::Chance, children: Vec::new() });
+        queue.push_back(game.clone());
+
+        while let Some(mut node) = queue.pop_front() {
+            let id = index[&node.hashkey()];
+            let mut children = Vec::new();
+            let kind = if let Some(result) = result_for_x(&node) {
+                NodeKind::Resolved(result)
+            } else {
+                outcomes.clear();
+                match node.to_move() {
+                    ToMove::Chance => node.generate_legal_moves_with_probabilities(&mut outcomes),
+                    _ => {
+                        buffer.clear();
+                        node.generate_moves(&mut buffer);
+                        outcomes.extend(buffer.iter().map(|&m| (m, 1.0)));
+                    }
+                }
+                for &(m, prob) in &outcomes {
+                    node.push(m);
+                    let next = nodes.len();
+                    let child = *index.entry(node.hashkey()).or_insert(next);
+                    if child == next {
+                        if nodes.len() >= max_states {
+                            return None;
+                        }
+                        nodes.push(Node { kind: NodeKind::Chance, children: Vec::new() });
+                        queue.push_back(node.clone());
+                    }
+                    node.pop(m);
+                    children.push((child, prob));
+                }
+                match node.to_move() {
+                    // stuck, as in rollout.
+                    _ if children.is_empty() => NodeKind::Resolved(0.5),
+                    ToMove::Max => NodeKind::Max,
+                    ToMove::Min => NodeKind::Min,
+                    ToMove::Chance => NodeKind::Chance,
+                }
+            };
+            nodes[id] = Node { kind, children };
+        }
+
+        let mut values = nodes
+            .iter()
+            .map(|node| match node.kind {
+                NodeKind::Resolved(value) => value,
+                _ => 0.0,
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..MAX_SWEEPS {
+            let mut change = 0.0f64;
+            for (id, node) in nodes.iter().enumerate() {
+                let children = node.children.iter().map(|&(child, prob)| (values[child], prob));
+                let value = match node.kind {
+                    NodeKind::Resolved(value) => value,
+                    NodeKind::Max => children.map(|(v, _)| v).fold(0.0, f64::max),
+                    NodeKind::Min => children.map(|(v, _)| v).fold(1.0, f64::min),
+                    NodeKind::Chance => children.map(|(v, p)| v * p).sum(),
+                };
+                change = change.max((value - values[id]).abs());
+                values[id] = value;
+            }
+            if change <= TOLERANCE {
+                break;
+            }
+        }
+
+        Some(Self { index, values })
+    }
+
+    pub fn states(&self) -> usize {
+        self.values.len()
+    }
+
+    // the chance that X wins from a position reachable from the root.
+    pub fn x_wins<G: Keyed>(&self, game: &G) -> Option<f64> {
+        self.index.get(&game.hashkey()).map(|&id| self.values[id])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveAnalysis<M> {
+    pub m: M,
+    // the win probability after the move is played.
+    pub value: WinProbability,
+}
+
+// works out win probabilities, exactly where the position is small enough,
+// and from rollouts with the policy otherwise.
+pub struct Analyser<P, R> {
+    // the most positions the exact solve may visit before giving up.
+    pub max_states: usize,
+    // the number of games played out for each estimate.
+    pub rollouts: u64,
+    pub policy: P,
+    pub rng: R,
+}
+
+impl<P, R: Rng> Analyser<P, R> {
+    pub fn new(policy: P, rng: R) -> Self {
+        Self {
+            max_states: 1 << 20,
+            rollouts: 1000,
+            policy,
+            rng,
+        }
+    }
+
+    pub fn with_max_states(self, max_states: usize) -> Self {
+        Self {
+            max_states,
+            ..self
+        }
+    }
+
+    pub fn with_rollouts(self, rollouts: u64) -> Self {
+        Self {
+            rollouts,
+            ..self
+        }
+    }
+
+    pub fn win_probability<G>(&mut self, game: &G) -> WinProbability
+    where
+        G: StochasticGame + Keyed,
+        P: Policy<G>,
+    {
+        match ExactSolution::solve(game, self.max_states) {
+            Some(solution) => WinProbability::exact(solution.x_wins(game).unwrap(), solution.states()),
+            None => self.estimate(game),
+        }
+    }
+
+    fn estimate<G>(&mut self, game: &G) -> WinProbability
+    where
+        G: StochasticGame,
+        P: Policy<G>,
+    {
+        let total = (0..self.rollouts)
+            .map(|_| rollout(game, &mut self.policy, &mut self.rng))
+            .sum::<f64>();
+        WinProbability::from_rollouts(total, self.rollouts)
+    }
+
+    // the win probability after each of the moves available once the chance
+    // move `roll` has been played from `game`, best first for the side to move.
+    pub fn analyse<G>(&mut self, game: &G, roll: G::Move) -> Vec<MoveAnalysis<G::Move>>
+    where
+        G: StochasticGame + Keyed,
+        P: Policy<G>,
+    {
+        let mut game = game.clone();
+        assert_eq!(game.to_move(), ToMove::Chance, "analyse called on a non-chance node");
+        game.push(roll);
+        let mut moves = Vec::with_capacity(game.action_space_size());
+        if !game.is_terminal() {
+            game.generate_moves(&mut moves);
+        }
+
+        // one solve covers every move, as they all lead back into the same graph.
+        let solution = ExactSolution::solve(&game, self.max_states);
+        let mut analysis = moves
+            .into_iter()
+            .map(|m| {
+                game.push(m);
+                let value = match &solution {
+                    Some(solution) => WinProbability::exact(solution.x_wins(&game).unwrap(), solution.states()),
+                    None => self.estimate(&game),
+                };
+                game.pop(m);
+                MoveAnalysis { m, value }
+            })
+            .collect::<Vec<_>>();
+        let player = if game.turn() == 1 { Player::X } else { Player::O };
+        analysis.sort_by(|a, b| b.value.for_player(player).total_cmp(&a.value.for_player(player)));
+        analysis
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::game::{Game, Player};
+    use crate::rgu::{Ur, UrMove};
+    use crate::solver::ExpectiConfig;
+
+    use super::{Analyser, ExactSolution, ExpectiPolicy, GreedyPolicy, Method, RandomPolicy, WinProbability};
+
+    // each side has one piece left on its last square, and X is to roll. any
+    // roll but a zero wins, so X wins with p = 15/16 + 1/16 * (1 - p).
+    fn race() -> Ur {
+        Ur::endgame(&[13], &[13], Player::X)
+    }
+
+    #[test]
+    fn exact_race() {
+        let solution = ExactSolution::solve(&race(), 1000).unwrap();
+        let p = solution.x_wins(&race()).unwrap();
+        assert!((p - 16.0 / 17.0).abs() < 1e-9);
+        // the position is too big to solve with a tiny budget.
+        assert!(ExactSolution::solve(&race(), 3).is_none());
+    }
+
+    #[test]
+    fn monte_carlo_race() {
+        let mut analyser = Analyser::new(RandomPolicy, StdRng::seed_from_u64(7))
+            .with_max_states(3)
+            .with_rollouts(4000);
+        let estimate = analyser.win_probability(&race());
+        assert_eq!(estimate.method, Method::MonteCarlo { rollouts: 4000 });
+        assert!(estimate.low < 16.0 / 17.0 && 16.0 / 17.0 < estimate.high);
+        assert!(estimate.low <= estimate.x_wins && estimate.x_wins <= estimate.high);
+    }
+
+    #[test]
+    fn analysis_after_a_roll() {
+        let game = Ur::endgame(&[13, 9], &[12], Player::X);
+        let mut analyser = Analyser::new(GreedyPolicy, StdRng::seed_from_u64(1));
+        let analysis = analyser.analyse(&game, UrMove::Roll(1));
+        // X can bear off from 13 or step from 9 to 10.
+        assert_eq!(analysis.len(), 2);
+        assert!(analysis[0].value.x_wins >= analysis[1].value.x_wins);
+        assert!(matches!(analysis[0].value.method, Method::Exact { .. }));
+
+        let solution = ExactSolution::solve(&game, 1 << 20).unwrap();
+        let mut rolled = game.clone();
+        rolled.push(UrMove::Roll(1));
+        assert!((solution.x_wins(&rolled).unwrap() - analysis[0].value.x_wins).abs() < 1e-9);
+    }
+
+    #[test]
+    fn policies_finish_games() {
+        let game = Ur::endgame(&[11, 6], &[10, 4], Player::O);
+        let mut analyser = Analyser::new(ExpectiPolicy(ExpectiConfig::new(1)), StdRng::seed_from_u64(3))
+            .with_max_states(0)
+            .with_rollouts(20);
+        let estimate = analyser.win_probability(&game);
+        assert!((0.0..=1.0).contains(&estimate.x_wins));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(WinProbability::exact(0.625, 10).to_string(), "X wins 62.5% (exact)");
+        let estimate = WinProbability::from_rollouts(50.0, 100);
+        assert_eq!(estimate.to_string(), "X wins 50.0% (95% CI 40.4% to 59.6%, 100 rollouts)");
+        assert_eq!(estimate.for_player(Player::O), 0.5);
+    }
+}